@@ -0,0 +1,225 @@
+use crate::error::Error;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_commitment_config::CommitmentConfig;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Cluster used when none is configured
+pub const DEFAULT_CLUSTER: &str = "devnet";
+
+/// Name of the profile built for an RPC URL that matches no known cluster
+pub const CUSTOM_CLUSTER: &str = "custom";
+
+/// x402 network of clusters that don't name one, which no payment is for
+pub const UNKNOWN_NETWORK: &str = "unknown";
+
+/// Commitment level RPC calls and confirmations wait for
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    Processed,
+    #[default]
+    Confirmed,
+    Finalized,
+}
+
+impl Commitment {
+    pub fn config(self) -> CommitmentConfig {
+        match self {
+            Commitment::Processed => CommitmentConfig::processed(),
+            Commitment::Confirmed => CommitmentConfig::confirmed(),
+            Commitment::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+/// Everything commands need to know about a cluster
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ClusterProfile {
+    pub name: String,
+    pub rpc_url: String,
+    /// Websocket endpoint for confirmations (derived from `rpc_url` when
+    /// unset)
+    pub ws_url: Option<String>,
+    /// Explorer page of a transaction, with `{signature}` in place of its
+    /// signature
+    pub explorer_url: Option<String>,
+    /// Whether the cluster airdrops SOL
+    pub faucet: bool,
+    pub commitment: Commitment,
+    /// x402 network name payments on this cluster are made for
+    pub network: String,
+}
+
+impl ClusterProfile {
+    /// Built-in profiles of the public clusters and `solana-test-validator`
+    pub fn builtin() -> Vec<Self> {
+        let profile = |name: &str,
+                       rpc_url: &str,
+                       explorer_url: &str,
+                       faucet,
+                       network: &str| Self {
+            name: name.to_string(),
+            rpc_url: rpc_url.to_string(),
+            ws_url: None,
+            explorer_url: Some(explorer_url.to_string()),
+            faucet,
+            commitment: Commitment::Confirmed,
+            network: network.to_string(),
+        };
+        vec![
+            profile(
+                "devnet",
+                "https://api.devnet.solana.com",
+                "https://explorer.solana.com/tx/{signature}?cluster=devnet",
+                true,
+                "solana-devnet",
+            ),
+            profile(
+                "testnet",
+                "https://api.testnet.solana.com",
+                "https://explorer.solana.com/tx/{signature}?cluster=testnet",
+                true,
+                "solana-testnet",
+            ),
+            profile(
+                "mainnet",
+                "https://api.mainnet-beta.solana.com",
+                "https://explorer.solana.com/tx/{signature}",
+                false,
+                "solana",
+            ),
+            profile(
+                "localnet",
+                "http://127.0.0.1:8899",
+                concat!(
+                    "https://explorer.solana.com/tx/{signature}",
+                    "?cluster=custom&customUrl=http%3A%2F%2F127.0.0.1%3A8899",
+                ),
+                true,
+                "solana-localnet",
+            ),
+        ]
+    }
+
+    /// Profile of an RPC endpoint that matches no known cluster. Nothing is
+    /// assumed about it: it has no faucet and its network is unknown.
+    pub fn custom(rpc_url: &str) -> Self {
+        Self {
+            name: CUSTOM_CLUSTER.to_string(),
+            rpc_url: rpc_url.to_string(),
+            ws_url: None,
+            explorer_url: None,
+            faucet: false,
+            commitment: Commitment::Confirmed,
+            network: UNKNOWN_NETWORK.to_string(),
+        }
+    }
+
+    /// Explorer page of `signature`, if the cluster has an explorer
+    pub fn explorer_link(
+        &self,
+        signature: &impl fmt::Display,
+    ) -> Option<String> {
+        self.explorer_url.as_ref().map(|template| {
+            template.replace("{signature}", &signature.to_string())
+        })
+    }
+
+    /// RPC client for the cluster at its commitment level
+    pub fn client(&self) -> RpcClient {
+        RpcClient::new_with_commitment(
+            self.rpc_url.clone(),
+            self.commitment.config(),
+        )
+    }
+}
+
+/// A user-defined profile, from a `[clusters.<name>]` table of the user config
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClusterConfig {
+    pub rpc_url: String,
+    pub ws_url: Option<String>,
+    pub explorer_url: Option<String>,
+    #[serde(default)]
+    pub faucet: bool,
+    #[serde(default)]
+    pub commitment: Commitment,
+    /// x402 network name, `unknown` when unset
+    pub network: Option<String>,
+}
+
+/// Known cluster profiles: the built-in ones and user-defined ones, which
+/// replace built-in profiles of the same name
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clusters {
+    profiles: Vec<ClusterProfile>,
+}
+
+impl Default for Clusters {
+    fn default() -> Self {
+        Self { profiles: ClusterProfile::builtin() }
+    }
+}
+
+impl Clusters {
+    pub fn new(user_defined: &BTreeMap<String, ClusterConfig>) -> Self {
+        let mut profiles: Vec<ClusterProfile> = ClusterProfile::builtin()
+            .into_iter()
+            .filter(|profile| !user_defined.contains_key(&profile.name))
+            .collect();
+        profiles.extend(user_defined.iter().map(|(name, config)| {
+            ClusterProfile {
+                name: name.clone(),
+                rpc_url: config.rpc_url.clone(),
+                ws_url: config.ws_url.clone(),
+                explorer_url: config.explorer_url.clone(),
+                faucet: config.faucet,
+                commitment: config.commitment,
+                network: config
+                    .network
+                    .clone()
+                    .unwrap_or_else(|| UNKNOWN_NETWORK.to_string()),
+            }
+        }));
+        Self { profiles }
+    }
+
+    pub fn profiles(&self) -> &[ClusterProfile] {
+        &self.profiles
+    }
+
+    pub fn get(&self, name: &str) -> Result<&ClusterProfile> {
+        self.profiles.iter().find(|profile| profile.name == name).ok_or_else(
+            || {
+                let names: Vec<&str> = self
+                    .profiles
+                    .iter()
+                    .map(|profile| profile.name.as_str())
+                    .collect();
+                Error::InvalidInput(format!(
+                    "Unknown cluster '{}'. Known clusters: {}",
+                    name,
+                    names.join(", ")
+                ))
+                .into()
+            },
+        )
+    }
+
+    /// The profile whose RPC URL is `rpc_url`, else a custom one
+    pub fn for_rpc_url(&self, rpc_url: &str) -> ClusterProfile {
+        let normalized = rpc_url.trim_end_matches('/');
+        self.profiles
+            .iter()
+            .find(|profile| {
+                profile.rpc_url.trim_end_matches('/') == normalized
+            })
+            .cloned()
+            .unwrap_or_else(|| ClusterProfile::custom(rpc_url))
+    }
+}
@@ -0,0 +1,589 @@
+use crate::cluster::{
+    ClusterConfig, ClusterProfile, Clusters, DEFAULT_CLUSTER,
+};
+use crate::error::Error;
+use crate::guard::{
+    SpendCaps, DEFAULT_MAX_SPEND_PER_DAY, DEFAULT_MAX_SPEND_PER_RUN,
+};
+use crate::manifest::{LimitsConfig, Manifest, RpcConfig};
+use crate::project::Project;
+use crate::report::{self, Reporter};
+use crate::retry::{self, RetryPolicy};
+use crate::utils::env_file::EnvFile;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Overrides the location of the user config file
+pub const CONFIG_PATH_VAR: &str = "XFORTH_CONFIG";
+
+/// User config file: settings shared by all projects
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct UserConfig {
+    #[serde(default)]
+    pub rpc: RpcConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// User-defined cluster profiles, by name
+    #[serde(default)]
+    pub clusters: BTreeMap<String, ClusterConfig>,
+}
+
+impl UserConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content)
+            .context(format!("Failed to parse {}", path.display()))
+    }
+}
+
+/// `$XFORTH_CONFIG`, else `xforth/config.toml` in `$XDG_CONFIG_HOME` or
+/// `~/.config`
+pub fn user_config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_PATH_VAR) {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir =
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some(config_dir.join("xforth").join("config.toml"))
+}
+
+/// Built-in cluster profiles and those of the user config file at
+/// [`user_config_path`]
+pub fn clusters() -> Result<Clusters> {
+    match user_config_path() {
+        Some(path) if path.is_file() => {
+            Ok(Clusters::new(&UserConfig::load(path)?.clusters))
+        }
+        _ => Ok(Clusters::default()),
+    }
+}
+
+/// Where the value of a setting came from, from highest to lowest precedence
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    /// A command-line flag
+    Flag(&'static str),
+    /// A variable of the process environment
+    Environment(&'static str),
+    /// The project's `.env`
+    ProjectEnv(PathBuf),
+    /// The project's `x402.toml`
+    Manifest(PathBuf),
+    UserConfig(PathBuf),
+    /// The profile of the selected cluster
+    Cluster(String),
+    Default,
+}
+
+impl Source {
+    /// Position in the precedence order, lowest first
+    fn rank(&self) -> u8 {
+        match self {
+            Source::Flag(_) => 0,
+            Source::Environment(_) => 1,
+            Source::ProjectEnv(_) => 2,
+            Source::Manifest(_) => 3,
+            Source::UserConfig(_) => 4,
+            Source::Cluster(_) => 5,
+            Source::Default => 6,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Flag(flag) => write!(f, "{} flag", flag),
+            Source::Environment(var) => write!(f, "environment ({})", var),
+            Source::ProjectEnv(path) => write!(f, ".env ({})", path.display()),
+            Source::Manifest(path) => {
+                write!(f, "x402.toml ({})", path.display())
+            }
+            Source::UserConfig(path) => {
+                write!(f, "user config ({})", path.display())
+            }
+            Source::Cluster(name) => write!(f, "cluster {}", name),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+impl Serialize for Source {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A resolved value and its source
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+/// Command-line flags that override configured values
+#[derive(Clone, Debug, Default)]
+pub struct ConfigFlags {
+    pub cluster: Option<String>,
+    /// `--local`, the same as `--cluster localnet`
+    pub local: bool,
+    pub rpc_url: Option<String>,
+    pub ws_url: Option<String>,
+    pub rpc_attempts: Option<u32>,
+    pub rpc_retry_delay_ms: Option<u64>,
+    pub rpc_retry_jitter: Option<f64>,
+    pub max_spend_per_run: Option<f64>,
+    pub max_spend_per_day: Option<f64>,
+}
+
+/// The sections of `x402.toml` and the user config file that hold settings
+#[derive(Clone, Copy)]
+struct Sections<'a> {
+    rpc: &'a RpcConfig,
+    limits: &'a LimitsConfig,
+}
+
+/// A configurable setting: its command-line flag, environment variable
+/// (also read from the project's `.env`) and field in the config files
+struct Key {
+    name: &'static str,
+    flag: &'static str,
+    var: &'static str,
+    field: fn(Sections) -> Option<String>,
+}
+
+const CLUSTER: Key = Key {
+    name: "cluster",
+    flag: "--cluster",
+    var: "XFORTH_CLUSTER",
+    field: |sections| sections.rpc.cluster.clone(),
+};
+
+const RPC_URL: Key = Key {
+    name: "rpc.url",
+    flag: "--rpc",
+    var: "RPC_URL",
+    field: |sections| sections.rpc.url.clone(),
+};
+
+const WS_URL: Key = Key {
+    name: "rpc.ws_url",
+    flag: "--ws",
+    var: "WS_URL",
+    field: |sections| sections.rpc.ws_url.clone(),
+};
+
+const RPC_ATTEMPTS: Key = Key {
+    name: "rpc.attempts",
+    flag: "--rpc-attempts",
+    var: "XFORTH_RPC_ATTEMPTS",
+    field: |sections| {
+        sections.rpc.attempts.map(|attempts| attempts.to_string())
+    },
+};
+
+const RPC_RETRY_DELAY: Key = Key {
+    name: "rpc.retry_delay_ms",
+    flag: "--rpc-retry-delay",
+    var: "XFORTH_RPC_RETRY_DELAY",
+    field: |sections| {
+        sections.rpc.retry_delay_ms.map(|delay| delay.to_string())
+    },
+};
+
+const RPC_RETRY_JITTER: Key = Key {
+    name: "rpc.retry_jitter",
+    flag: "--rpc-retry-jitter",
+    var: "XFORTH_RPC_RETRY_JITTER",
+    field: |sections| {
+        sections.rpc.retry_jitter.map(|jitter| jitter.to_string())
+    },
+};
+
+const MAX_SPEND_PER_RUN: Key = Key {
+    name: "limits.max_spend_per_run",
+    flag: "--max-spend-per-run",
+    var: "XFORTH_MAX_SPEND_PER_RUN",
+    field: |sections| {
+        sections.limits.max_spend_per_run.map(|sol| sol.to_string())
+    },
+};
+
+const MAX_SPEND_PER_DAY: Key = Key {
+    name: "limits.max_spend_per_day",
+    flag: "--max-spend-per-day",
+    var: "XFORTH_MAX_SPEND_PER_DAY",
+    field: |sections| {
+        sections.limits.max_spend_per_day.map(|sol| sol.to_string())
+    },
+};
+
+/// Effective configuration of a command.
+///
+/// Each setting comes from the first of: command-line flags, the process
+/// environment, the project's `.env`, the project's `x402.toml`, the user
+/// config file, and built-in defaults. An RPC URL set at the same level as
+/// a cluster or above it wins over the cluster's, and selects the profile
+/// with that URL, if any.
+#[derive(Clone, Debug, Serialize)]
+pub struct Config {
+    /// The selected profile, with the effective RPC and websocket URLs
+    pub cluster: Setting<ClusterProfile>,
+    pub rpc_url: Setting<String>,
+    /// `None` to derive the websocket endpoint from the RPC URL
+    pub ws_url: Setting<Option<String>>,
+    pub rpc_attempts: Setting<u32>,
+    pub rpc_retry_delay_ms: Setting<u64>,
+    pub rpc_retry_jitter: Setting<f64>,
+    /// Spend caps on mainnet, in SOL
+    pub max_spend_per_run: Setting<f64>,
+    pub max_spend_per_day: Setting<f64>,
+}
+
+impl Config {
+    /// Resolve the configuration of `project`, with the user config file at
+    /// [`user_config_path`]
+    pub fn resolve(flags: &ConfigFlags, project: &Project) -> Result<Self> {
+        Self::resolve_with(flags, project, user_config_path().as_deref())
+    }
+
+    /// Resolve the configuration of `project` with the user config file at
+    /// `user_config`
+    pub fn resolve_with(
+        flags: &ConfigFlags,
+        project: &Project,
+        user_config: Option<&Path>,
+    ) -> Result<Self> {
+        let project_env = EnvFile::load(project.env_path())?;
+        let manifest_path = project.manifest_path();
+        let manifest = if manifest_path.is_file() {
+            Some(Manifest::load(&manifest_path)?)
+        } else {
+            None
+        };
+        let user = match user_config {
+            Some(path) if path.is_file() => {
+                Some((path.to_path_buf(), UserConfig::load(path)?))
+            }
+            _ => None,
+        };
+
+        let clusters = match &user {
+            Some((_, config)) => Clusters::new(&config.clusters),
+            None => Clusters::default(),
+        };
+
+        let layers = Layers {
+            project_env: &project_env,
+            manifest: manifest.as_ref().map(|manifest| {
+                (
+                    manifest_path.as_path(),
+                    Sections { rpc: &manifest.rpc, limits: &manifest.limits },
+                )
+            }),
+            user: user.as_ref().map(|(path, config)| {
+                (
+                    path.as_path(),
+                    Sections { rpc: &config.rpc, limits: &config.limits },
+                )
+            }),
+        };
+
+        let cluster = match (&flags.cluster, flags.local) {
+            (None, true) => Some(Setting {
+                value: "localnet".to_string(),
+                source: Source::Flag("--local"),
+            }),
+            (cluster, _) => layers.resolve(&CLUSTER, cluster.clone())?,
+        };
+        let rpc_url = layers.resolve(&RPC_URL, flags.rpc_url.clone())?;
+        let rpc_url_wins = match (&rpc_url, &cluster) {
+            (Some(rpc_url), Some(cluster)) => {
+                rpc_url.source.rank() <= cluster.source.rank()
+            }
+            (rpc_url, _) => rpc_url.is_some(),
+        };
+
+        let (mut cluster, rpc_url) = match rpc_url {
+            Some(rpc_url) if rpc_url_wins => {
+                let profile = clusters.for_rpc_url(&rpc_url.value);
+                (
+                    Setting { value: profile, source: rpc_url.source.clone() },
+                    rpc_url,
+                )
+            }
+            _ => {
+                let cluster = cluster.unwrap_or_else(|| Setting {
+                    value: DEFAULT_CLUSTER.to_string(),
+                    source: Source::Default,
+                });
+                let profile = clusters.get(&cluster.value)?.clone();
+                let rpc_url = Setting {
+                    value: profile.rpc_url.clone(),
+                    source: Source::Cluster(profile.name.clone()),
+                };
+                (Setting { value: profile, source: cluster.source }, rpc_url)
+            }
+        };
+
+        let ws_url = match layers.resolve(&WS_URL, flags.ws_url.clone())? {
+            Some(Setting { value, source }) => {
+                Setting { value: Some(value), source }
+            }
+            None => match &cluster.value.ws_url {
+                Some(ws_url) => Setting {
+                    value: Some(ws_url.clone()),
+                    source: Source::Cluster(cluster.value.name.clone()),
+                },
+                None => Setting { value: None, source: Source::Default },
+            },
+        };
+        cluster.value.rpc_url = rpc_url.value.clone();
+        cluster.value.ws_url = ws_url.value.clone();
+        let rpc_attempts = layers
+            .resolve(&RPC_ATTEMPTS, flags.rpc_attempts)?
+            .unwrap_or(Setting {
+                value: retry::DEFAULT_MAX_ATTEMPTS,
+                source: Source::Default,
+            });
+        if rpc_attempts.value == 0 {
+            return Err(Error::InvalidInput(format!(
+                "{} must be at least 1 (from {})",
+                RPC_ATTEMPTS.name, rpc_attempts.source
+            ))
+            .into());
+        }
+        let rpc_retry_delay_ms = layers
+            .resolve(&RPC_RETRY_DELAY, flags.rpc_retry_delay_ms)?
+            .unwrap_or(Setting {
+                value: retry::DEFAULT_BASE_DELAY_MS,
+                source: Source::Default,
+            });
+        let rpc_retry_jitter = layers
+            .resolve(&RPC_RETRY_JITTER, flags.rpc_retry_jitter)?
+            .unwrap_or(Setting {
+                value: retry::DEFAULT_JITTER,
+                source: Source::Default,
+            });
+
+        let spend_cap = |key: &Key,
+                         flag: Option<f64>,
+                         default: f64|
+         -> Result<Setting<f64>> {
+            let cap = layers.resolve(key, flag)?.unwrap_or(Setting {
+                value: default,
+                source: Source::Default,
+            });
+            if !cap.value.is_finite() || cap.value < 0.0 {
+                return Err(Error::InvalidInput(format!(
+                    "{} must be a non-negative amount of SOL (from {})",
+                    key.name, cap.source
+                ))
+                .into());
+            }
+            Ok(cap)
+        };
+        let max_spend_per_run = spend_cap(
+            &MAX_SPEND_PER_RUN,
+            flags.max_spend_per_run,
+            DEFAULT_MAX_SPEND_PER_RUN,
+        )?;
+        let max_spend_per_day = spend_cap(
+            &MAX_SPEND_PER_DAY,
+            flags.max_spend_per_day,
+            DEFAULT_MAX_SPEND_PER_DAY,
+        )?;
+
+        Ok(Self {
+            cluster,
+            rpc_url,
+            ws_url,
+            rpc_attempts,
+            rpc_retry_delay_ms,
+            rpc_retry_jitter,
+            max_spend_per_run,
+            max_spend_per_day,
+        })
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.rpc_attempts.value,
+            base_delay: Duration::from_millis(self.rpc_retry_delay_ms.value),
+            jitter: self.rpc_retry_jitter.value.clamp(0.0, 1.0),
+        }
+    }
+
+    /// `(name, value, source)` of every setting, for `xforth config show`
+    pub fn entries(&self) -> Vec<(&'static str, String, &Source)> {
+        vec![
+            (
+                CLUSTER.name,
+                self.cluster.value.name.clone(),
+                &self.cluster.source,
+            ),
+            (RPC_URL.name, self.rpc_url.value.clone(), &self.rpc_url.source),
+            (
+                WS_URL.name,
+                self.ws_url
+                    .value
+                    .clone()
+                    .unwrap_or_else(|| "(derived from rpc.url)".to_string()),
+                &self.ws_url.source,
+            ),
+            (
+                RPC_ATTEMPTS.name,
+                self.rpc_attempts.value.to_string(),
+                &self.rpc_attempts.source,
+            ),
+            (
+                RPC_RETRY_DELAY.name,
+                self.rpc_retry_delay_ms.value.to_string(),
+                &self.rpc_retry_delay_ms.source,
+            ),
+            (
+                RPC_RETRY_JITTER.name,
+                self.rpc_retry_jitter.value.to_string(),
+                &self.rpc_retry_jitter.source,
+            ),
+            (
+                MAX_SPEND_PER_RUN.name,
+                self.max_spend_per_run.value.to_string(),
+                &self.max_spend_per_run.source,
+            ),
+            (
+                MAX_SPEND_PER_DAY.name,
+                self.max_spend_per_day.value.to_string(),
+                &self.max_spend_per_day.source,
+            ),
+        ]
+    }
+
+    /// Spend caps enforced on mainnet
+    pub fn spend_caps(&self) -> SpendCaps {
+        SpendCaps::from_sol(
+            self.max_spend_per_run.value,
+            self.max_spend_per_day.value,
+        )
+    }
+
+    /// Details of the selected cluster profile, for `config show`
+    fn profile_entries(&self) -> Vec<(&'static str, String)> {
+        let profile = &self.cluster.value;
+        vec![
+            (
+                "cluster.explorer_url",
+                profile
+                    .explorer_url
+                    .clone()
+                    .unwrap_or_else(|| "(none)".to_string()),
+            ),
+            ("cluster.faucet", profile.faucet.to_string()),
+            (
+                "cluster.commitment",
+                format!("{:?}", profile.commitment).to_lowercase(),
+            ),
+            ("cluster.network", profile.network.clone()),
+        ]
+    }
+}
+
+/// The configuration sources below command-line flags
+struct Layers<'a> {
+    project_env: &'a EnvFile,
+    manifest: Option<(&'a Path, Sections<'a>)>,
+    user: Option<(&'a Path, Sections<'a>)>,
+}
+
+impl Layers<'_> {
+    /// The flag's value if set, else the first configured value of `key`
+    fn resolve<T>(
+        &self,
+        key: &Key,
+        flag: Option<T>,
+    ) -> Result<Option<Setting<T>>>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        if let Some(value) = flag {
+            return Ok(Some(Setting {
+                value,
+                source: Source::Flag(key.flag),
+            }));
+        }
+
+        // Empty variables count as unset
+        let configured = env::var(key.var)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| (value, Source::Environment(key.var)))
+            .or_else(|| {
+                self.project_env
+                    .get(key.var)
+                    .filter(|value| !value.trim().is_empty())
+                    .map(|value| {
+                        (
+                            value.to_string(),
+                            Source::ProjectEnv(
+                                self.project_env.path().to_path_buf(),
+                            ),
+                        )
+                    })
+            })
+            .or_else(|| {
+                self.manifest.and_then(|(path, sections)| {
+                    (key.field)(sections).map(|value| {
+                        (value, Source::Manifest(path.to_path_buf()))
+                    })
+                })
+            })
+            .or_else(|| {
+                self.user.and_then(|(path, sections)| {
+                    (key.field)(sections).map(|value| {
+                        (value, Source::UserConfig(path.to_path_buf()))
+                    })
+                })
+            });
+
+        match configured {
+            Some((value, source)) => {
+                let parsed = value.trim().parse().map_err(|err| {
+                    Error::InvalidInput(format!(
+                        "Invalid {} '{}' from {}: {}",
+                        key.name, value, source, err
+                    ))
+                })?;
+                Ok(Some(Setting { value: parsed, source }))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// `xforth config show`: every setting with its value and source
+pub fn show(config: &Config, reporter: &dyn Reporter) {
+    for (name, value, source) in config.entries() {
+        reporter.note(&format!("{} = {}  ({})", name, value, source));
+    }
+    for (name, value) in config.profile_entries() {
+        reporter.note(&format!(
+            "{} = {}  (cluster {})",
+            name, value, config.cluster.value.name
+        ));
+    }
+    reporter.result(report::success("config show", config));
+}
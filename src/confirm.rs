@@ -0,0 +1,404 @@
+use crate::cluster::ClusterProfile;
+use crate::error::Error;
+use crate::guard::{self, SpendGuard};
+use crate::report::Reporter;
+use crate::rpc::RpcApi;
+use anyhow::{Context, Result};
+use futures_util::stream::{BoxStream, StreamExt};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_commitment_config::CommitmentConfig;
+use solana_rpc_client_api::{
+    config::RpcSignatureSubscribeConfig,
+    response::{Response as RpcResponse, RpcSignatureResult},
+};
+use solana_sdk::{
+    hash::Hash,
+    signature::{Keypair, Signature},
+    transaction::{Transaction, TransactionError},
+};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+/// How often the signature status is polled without a websocket subscription
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often the status is still checked while subscribed, in case a
+/// notification is missed or the blockhash expires
+pub const SUBSCRIBED_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Times a transaction is re-signed and resubmitted after its blockhash
+/// expired
+pub const MAX_RESUBMITS: usize = 3;
+
+/// Outcome of waiting for a signature
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Confirmation {
+    Confirmed,
+    Failed(TransactionError),
+    /// The blockhash expired before the transaction landed, so it never will
+    Expired,
+}
+
+impl From<Result<(), TransactionError>> for Confirmation {
+    fn from(status: Result<(), TransactionError>) -> Self {
+        match status {
+            Ok(()) => Confirmation::Confirmed,
+            Err(err) => Confirmation::Failed(err),
+        }
+    }
+}
+
+/// Waits for transactions to confirm.
+///
+/// With a websocket URL, confirmations come from a `signatureSubscribe`
+/// subscription at the cluster's commitment, over one connection shared by
+/// every confirmation; if it can't be opened, or without one, the signature
+/// status is polled instead. Either way a transaction is given up on only
+/// once its blockhash has expired, rather than after a fixed timeout.
+///
+/// With a [`SpendGuard`], every transaction is checked against its caps
+/// before it is sent and recorded once it lands.
+#[derive(Clone, Debug)]
+pub struct Confirmer {
+    ws_url: Option<String>,
+    commitment: CommitmentConfig,
+    poll_interval: Duration,
+    spend_guard: Option<Arc<SpendGuard>>,
+    pubsub: SharedPubsub,
+}
+
+impl Default for Confirmer {
+    /// Polling only
+    fn default() -> Self {
+        Self {
+            ws_url: None,
+            commitment: CommitmentConfig::confirmed(),
+            poll_interval: POLL_INTERVAL,
+            spend_guard: None,
+            pubsub: SharedPubsub::default(),
+        }
+    }
+}
+
+/// Websocket connection opened by the first confirmation and shared by the
+/// ones after it, including those of clones of the [`Confirmer`]
+#[derive(Clone, Default)]
+struct SharedPubsub(Arc<Mutex<Option<Arc<PubsubClient>>>>);
+
+impl SharedPubsub {
+    /// The open connection, connecting to `url` if there is none
+    async fn connect(&self, url: &str) -> Option<Arc<PubsubClient>> {
+        let mut pubsub = self.0.lock().await;
+        if pubsub.is_none() {
+            *pubsub = PubsubClient::new(url).await.ok().map(Arc::new);
+        }
+        pubsub.clone()
+    }
+
+    /// Drop a connection that failed, so the next confirmation reconnects
+    async fn reset(&self, failed: &Arc<PubsubClient>) {
+        let mut pubsub = self.0.lock().await;
+        if pubsub.as_ref().is_some_and(|open| Arc::ptr_eq(open, failed)) {
+            *pubsub = None;
+        }
+    }
+}
+
+impl fmt::Debug for SharedPubsub {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedPubsub")
+    }
+}
+
+impl Confirmer {
+    pub fn new(ws_url: Option<String>) -> Self {
+        Self { ws_url, ..Default::default() }
+    }
+
+    /// Subscribe on the cluster's websocket URL if set, otherwise on the
+    /// websocket endpoint of its RPC URL, at its commitment level
+    pub fn for_cluster(cluster: &ClusterProfile) -> Result<Self> {
+        let ws_url = match &cluster.ws_url {
+            Some(url) => url.clone(),
+            None => websocket_url(&cluster.rpc_url)?,
+        };
+        Ok(Self::new(Some(ws_url))
+            .with_commitment(cluster.commitment.config()))
+    }
+
+    pub fn with_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn with_spend_guard(mut self, spend_guard: Arc<SpendGuard>) -> Self {
+        self.spend_guard = Some(spend_guard);
+        self
+    }
+
+    pub fn ws_url(&self) -> Option<&str> {
+        self.ws_url.as_deref()
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.commitment
+    }
+
+    pub fn spend_guard(&self) -> Option<&SpendGuard> {
+        self.spend_guard.as_deref()
+    }
+
+    /// Estimated cost of `transaction`, failing if it would exceed the spend
+    /// caps
+    async fn check_spend(
+        &self,
+        client: &impl RpcApi,
+        transaction: &Transaction,
+    ) -> Result<u64> {
+        let Some(spend_guard) = &self.spend_guard else { return Ok(0) };
+        let cost = guard::estimate_cost(client, transaction).await?;
+        spend_guard.check(cost)?;
+        Ok(cost)
+    }
+
+    /// Record what a landed transaction spent; failed ones still pay their fee
+    fn record_spend(
+        &self,
+        signature: &Signature,
+        transaction: &Transaction,
+        cost: u64,
+        confirmation: &Confirmation,
+    ) -> Result<()> {
+        let Some(spend_guard) = &self.spend_guard else { return Ok(()) };
+        match confirmation {
+            Confirmation::Confirmed => spend_guard.record(signature, cost),
+            Confirmation::Failed(_) => {
+                spend_guard.record(signature, guard::fee(transaction))
+            }
+            Confirmation::Expired => Ok(()),
+        }
+    }
+
+    /// Wait until `signature` is confirmed, fails, or `recent_blockhash`
+    /// expires
+    pub async fn confirm(
+        &self,
+        client: &impl RpcApi,
+        signature: &Signature,
+        recent_blockhash: &Hash,
+    ) -> Result<Confirmation> {
+        let pubsub = match self.ws_url.as_deref() {
+            Some(url) => self.pubsub.connect(url).await,
+            None => None,
+        };
+        let Some(pubsub) = pubsub else {
+            return self.wait(client, signature, recent_blockhash, None).await;
+        };
+
+        let config = RpcSignatureSubscribeConfig {
+            commitment: Some(self.commitment),
+            enable_received_notification: Some(false),
+        };
+        let subscription =
+            pubsub.signature_subscribe(signature, Some(config)).await;
+        let confirmation = match subscription {
+            Ok((notifications, unsubscribe)) => {
+                let confirmation = self
+                    .wait(
+                        client,
+                        signature,
+                        recent_blockhash,
+                        Some(notifications),
+                    )
+                    .await;
+                unsubscribe().await;
+                confirmation
+            }
+            Err(_) => {
+                self.pubsub.reset(&pubsub).await;
+                self.wait(client, signature, recent_blockhash, None).await
+            }
+        };
+        confirmation
+    }
+
+    async fn wait(
+        &self,
+        client: &impl RpcApi,
+        signature: &Signature,
+        recent_blockhash: &Hash,
+        mut notifications: Option<
+            BoxStream<'_, RpcResponse<RpcSignatureResult>>,
+        >,
+    ) -> Result<Confirmation> {
+        loop {
+            if let Some(status) =
+                client.get_signature_status(signature).await?
+            {
+                return Ok(status.into());
+            }
+            if !client.is_blockhash_valid(recent_blockhash).await? {
+                // It may have landed just before the blockhash expired
+                return Ok(
+                    match client.get_signature_status(signature).await? {
+                        Some(status) => status.into(),
+                        None => Confirmation::Expired,
+                    },
+                );
+            }
+
+            let Some(stream) = notifications.as_mut() else {
+                sleep(self.poll_interval).await;
+                continue;
+            };
+            tokio::select! {
+                notification = stream.next() => match notification {
+                    Some(RpcResponse {
+                        value: RpcSignatureResult::ProcessedSignature(result),
+                        ..
+                    }) => {
+                        return Ok(match result.err {
+                            Some(err) => Confirmation::Failed(err.into()),
+                            None => Confirmation::Confirmed,
+                        });
+                    }
+                    Some(_) => {}
+                    // The subscription was dropped, keep polling
+                    None => notifications = None,
+                },
+                _ = sleep(SUBSCRIBED_CHECK_INTERVAL) => {}
+            }
+        }
+    }
+
+    /// Send `transaction` and wait for it to confirm. Whenever its blockhash
+    /// expires first, it is re-signed by `signers` with a fresh blockhash and
+    /// resubmitted, up to [`MAX_RESUBMITS`] times.
+    ///
+    /// Every send and the confirmation are reported under `label`.
+    pub async fn send_and_confirm(
+        &self,
+        client: &impl RpcApi,
+        transaction: &mut Transaction,
+        signers: &[&Keypair],
+        label: &str,
+        reporter: &dyn Reporter,
+    ) -> Result<Signature> {
+        let mut resubmits = 0;
+        loop {
+            let cost = self.check_spend(client, transaction).await?;
+            let signature = client.send_transaction(transaction).await?;
+            reporter.transaction_sent(label, &signature);
+            let confirmation = self
+                .confirm(
+                    client,
+                    &signature,
+                    &transaction.message.recent_blockhash,
+                )
+                .await?;
+            self.record_spend(&signature, transaction, cost, &confirmation)?;
+            match confirmation {
+                Confirmation::Confirmed => {
+                    reporter.transaction_confirmed(label, &signature);
+                    return Ok(signature);
+                }
+                Confirmation::Failed(err) => {
+                    return Err(Error::TransactionFailed(err).into())
+                }
+                Confirmation::Expired if resubmits == MAX_RESUBMITS => {
+                    return Err(expired(&signature))
+                }
+                Confirmation::Expired => {
+                    reporter.warning(&format!(
+                        "{} transaction {} expired before it was confirmed, \
+                            resubmitting...",
+                        label, signature
+                    ));
+                    let recent_blockhash =
+                        client.get_latest_blockhash().await?;
+                    transaction.try_sign(signers, recent_blockhash).context(
+                        format!(
+                            "Failed to re-sign expired transaction {}",
+                            signature
+                        ),
+                    )?;
+                    resubmits += 1;
+                }
+            }
+        }
+    }
+
+    /// Send a transaction that can't be re-signed and wait for it to confirm
+    pub async fn submit(
+        &self,
+        client: &impl RpcApi,
+        transaction: &Transaction,
+    ) -> Result<Signature> {
+        match self.send(client, transaction).await? {
+            (signature, Confirmation::Confirmed) => Ok(signature),
+            (_, Confirmation::Failed(err)) => {
+                Err(Error::TransactionFailed(err).into())
+            }
+            (signature, Confirmation::Expired) => Err(expired(&signature)),
+        }
+    }
+
+    async fn send(
+        &self,
+        client: &impl RpcApi,
+        transaction: &Transaction,
+    ) -> Result<(Signature, Confirmation)> {
+        let cost = self.check_spend(client, transaction).await?;
+        let signature = client.send_transaction(transaction).await?;
+        let confirmation = self
+            .confirm(client, &signature, &transaction.message.recent_blockhash)
+            .await?;
+        self.record_spend(&signature, transaction, cost, &confirmation)?;
+        Ok((signature, confirmation))
+    }
+}
+
+fn expired(signature: &Signature) -> anyhow::Error {
+    Error::TransactionExpired(format!(
+        "Transaction {} expired before it was confirmed",
+        signature
+    ))
+    .into()
+}
+
+/// Websocket endpoint of an RPC URL, following the Solana CLI: `http` becomes
+/// `ws`, `https` becomes `wss`, and an explicit port is incremented by one.
+pub fn websocket_url(rpc_url: &str) -> Result<String> {
+    let mut url = reqwest::Url::parse(rpc_url)
+        .context(format!("Invalid RPC URL: {}", rpc_url))?;
+    let scheme = match url.scheme() {
+        "http" | "ws" => "ws",
+        "https" | "wss" => "wss",
+        other => {
+            return Err(anyhow::anyhow!(
+                "Unsupported RPC URL scheme: {}",
+                other
+            ))
+        }
+    };
+    if let Some(port) = url.port() {
+        url.set_port(Some(
+            port.checked_add(1).context("RPC port is out of range")?,
+        ))
+        .map_err(|_| {
+            anyhow::anyhow!("Cannot derive a websocket URL from {}", rpc_url)
+        })?;
+    }
+    url.set_scheme(scheme).map_err(|_| {
+        anyhow::anyhow!("Cannot derive a websocket URL from {}", rpc_url)
+    })?;
+    Ok(url.to_string())
+}
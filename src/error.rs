@@ -0,0 +1,123 @@
+use solana_rpc_client_api::client_error::{
+    Error as ClientError, ErrorKind as ClientErrorKind,
+};
+use solana_sdk::transaction::TransactionError;
+use thiserror::Error;
+
+/// Failures commands report with a stable code and exit code.
+///
+/// Commands return `anyhow` errors; these are attached at the root or as
+/// context so that [`classify`] can find them behind any added context.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(
+        "Failed to load .env file. Make sure you're in the project \
+        directory and have run 'xforth init' first."
+    )]
+    EnvFileMissing,
+    #[error("Environment variable {0} not found")]
+    EnvVarMissing(String),
+    #[error("{0} does not hold a valid keypair")]
+    KeypairInvalid(String),
+    #[error("{0}")]
+    PassphraseMissing(String),
+    #[error("{0}")]
+    PassphraseInvalid(String),
+    #[error("{0}")]
+    InsufficientBalance(String),
+    #[error("Airdrop rate limited: {0}")]
+    AirdropRateLimited(String),
+    #[error("{0}")]
+    AirdropUnsupported(String),
+    #[error("RPC endpoint is unreachable: {0}")]
+    RpcUnreachable(String),
+    #[error("RPC request failed: {0}")]
+    Rpc(String),
+    #[error("Transaction failed: {0:?}")]
+    TransactionFailed(TransactionError),
+    #[error("{0}")]
+    TransactionExpired(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("{0}")]
+    PaymentFailed(String),
+    #[error("{0}")]
+    MainnetNotAllowed(String),
+    #[error("Spend cap exceeded: {0}")]
+    SpendCapExceeded(String),
+}
+
+impl Error {
+    /// Stable code reported as `code` in JSON output
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::EnvFileMissing | Error::EnvVarMissing(_) => "env_missing",
+            Error::KeypairInvalid(_) => "keypair_invalid",
+            Error::PassphraseMissing(_) => "passphrase_missing",
+            Error::PassphraseInvalid(_) => "passphrase_invalid",
+            Error::InsufficientBalance(_) => "insufficient_balance",
+            Error::AirdropRateLimited(_) => "airdrop_rate_limited",
+            Error::AirdropUnsupported(_) => "airdrop_unsupported",
+            Error::RpcUnreachable(_) => "rpc_unreachable",
+            Error::Rpc(_) => "rpc_error",
+            Error::TransactionFailed(_) => "transaction_failed",
+            Error::TransactionExpired(_) => "transaction_expired",
+            Error::NotFound(_) => "not_found",
+            Error::InvalidInput(_) => "invalid_input",
+            Error::PaymentFailed(_) => "payment_failed",
+            Error::MainnetNotAllowed(_) => "mainnet_not_allowed",
+            Error::SpendCapExceeded(_) => "spend_cap_exceeded",
+        }
+    }
+
+    /// Process exit code, one per class of failure. 1 is left for
+    /// unclassified errors and 2 matches clap's usage errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidInput(_) => 2,
+            Error::EnvFileMissing
+            | Error::EnvVarMissing(_)
+            | Error::KeypairInvalid(_) => 3,
+            Error::PassphraseMissing(_) | Error::PassphraseInvalid(_) => 3,
+            Error::InsufficientBalance(_) => 4,
+            Error::RpcUnreachable(_) | Error::Rpc(_) => 5,
+            Error::AirdropRateLimited(_) | Error::AirdropUnsupported(_) => 6,
+            Error::TransactionFailed(_) | Error::TransactionExpired(_) => 7,
+            Error::NotFound(_) => 8,
+            Error::PaymentFailed(_) => 9,
+            Error::MainnetNotAllowed(_) | Error::SpendCapExceeded(_) => 10,
+        }
+    }
+}
+
+impl From<&ClientError> for Error {
+    fn from(err: &ClientError) -> Self {
+        if let Some(tx_err) = err.get_transaction_error() {
+            return Error::TransactionFailed(tx_err);
+        }
+        match err.kind() {
+            ClientErrorKind::Io(_) => Error::RpcUnreachable(err.to_string()),
+            ClientErrorKind::Reqwest(reqwest_err)
+                if reqwest_err.is_connect() || reqwest_err.is_timeout() =>
+            {
+                Error::RpcUnreachable(err.to_string())
+            }
+            _ => Error::Rpc(err.to_string()),
+        }
+    }
+}
+
+/// Code and exit code of a command failure: those of the [`Error`] it
+/// carries, else of the RPC error it was caused by, else `internal` and 1
+pub fn classify(err: &anyhow::Error) -> (&'static str, i32) {
+    if let Some(err) = err.downcast_ref::<Error>() {
+        return (err.code(), err.exit_code());
+    }
+    if let Some(err) = err.downcast_ref::<ClientError>() {
+        let err = Error::from(err);
+        return (err.code(), err.exit_code());
+    }
+    ("internal", 1)
+}
@@ -0,0 +1,747 @@
+use crate::cluster::ClusterProfile;
+use crate::confirm::Confirmer;
+use crate::guard::ComputeBudget;
+use crate::keystore::load_keypair;
+use crate::ledger::{Ledger, LedgerEntry};
+use crate::project::Project;
+use crate::report::Reporter;
+use crate::retry::{RetryPolicy, RetryingRpc};
+use crate::rpc::RpcApi;
+use crate::token::{self, TokenProgram};
+use crate::utils::truncate_pubkey;
+use crate::x402::{
+    FacilitatorRequest, SettlementResponse, SupportedKind, SupportedResponse,
+    VerifyResponse, X402Error, MAX_NONCE_LEN, MEMO_PROGRAM_ID, SCHEME_EXACT,
+    X402_VERSION,
+};
+use anyhow::{Context, Result};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::json;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sanitize::Sanitize;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+};
+use spl_token_2022_interface::instruction::TokenInstruction;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Default address the facilitator listens on
+pub const DEFAULT_BIND: &str = "127.0.0.1:3402";
+
+/// Instruction tag of the associated token account program's
+/// `CreateIdempotent`
+const ATA_CREATE_IDEMPOTENT: u8 = 1;
+
+/// Highest compute unit limit a payment may request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 200_000;
+
+/// Highest priority fee a payment may set, in micro-lamports per compute
+/// unit. The facilitator pays it, and a price needs an explicit limit, so
+/// this caps the priority fee at 20,000 lamports.
+pub const MAX_COMPUTE_UNIT_PRICE: u64 = 100_000;
+
+/// Reasons a payment is rejected, each with a stable code
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error(transparent)]
+    Payload(#[from] X402Error),
+    #[error(
+        "payment is for network '{0}' but this facilitator settles '{1}'"
+    )]
+    NetworkMismatch(String, String),
+    #[error("transaction signatures are missing or invalid")]
+    InvalidSignature,
+    #[error("fee payer {0} is not this facilitator")]
+    FeePayerMismatch(Pubkey),
+    #[error("instruction {0} uses the facilitator's account")]
+    FeePayerInInstruction(usize),
+    #[error("instruction {0} calls unsupported program {1}")]
+    UnexpectedInstruction(usize, Pubkey),
+    #[error(
+        "instruction {0} sets a compute budget other than a limit of at most \
+            {MAX_COMPUTE_UNIT_LIMIT} units and a price of at most \
+            {MAX_COMPUTE_UNIT_PRICE} micro-lamports per unit"
+    )]
+    ComputeBudget(usize),
+    #[error("instruction {0} is not a valid payment nonce memo")]
+    InvalidMemo(usize),
+    #[error("payment {0} has already been settled")]
+    DuplicatePayment(String),
+    #[error("expected exactly one transfer_checked instruction, found {0}")]
+    TransferCount(usize),
+    #[error("transfer mint {0} does not match the required asset")]
+    MintMismatch(Pubkey),
+    #[error("transfer destination {0} is not the payTo token account")]
+    RecipientMismatch(Pubkey),
+    #[error("transfer amount {0} does not match the required amount {1}")]
+    AmountMismatch(u64, u64),
+    #[error("transaction blockhash is expired or unknown")]
+    BlockhashExpired,
+    #[error("transaction simulation failed: {0}")]
+    SimulationFailed(String),
+    #[error("rpc error: {0}")]
+    Rpc(#[from] solana_client::client_error::ClientError),
+}
+
+impl VerifyError {
+    /// Stable code reported as `invalidReason`
+    pub fn code(&self) -> &'static str {
+        match self {
+            VerifyError::Payload(_) => "invalid_payload",
+            VerifyError::NetworkMismatch(..) => "invalid_network",
+            VerifyError::InvalidSignature => "invalid_signature",
+            VerifyError::FeePayerMismatch(_) => "fee_payer_mismatch",
+            VerifyError::FeePayerInInstruction(_) => {
+                "fee_payer_in_instruction"
+            }
+            VerifyError::UnexpectedInstruction(..) => "invalid_instruction",
+            VerifyError::ComputeBudget(_) => "invalid_compute_budget",
+            VerifyError::InvalidMemo(_) => "invalid_memo",
+            VerifyError::DuplicatePayment(_) => "duplicate_payment",
+            VerifyError::TransferCount(_) => "invalid_transfer",
+            VerifyError::MintMismatch(_) => "asset_mismatch",
+            VerifyError::RecipientMismatch(_) => "recipient_mismatch",
+            VerifyError::AmountMismatch(..) => "amount_mismatch",
+            VerifyError::BlockhashExpired => "blockhash_expired",
+            VerifyError::SimulationFailed(_) => "simulation_failed",
+            VerifyError::Rpc(_) => "rpc_error",
+        }
+    }
+}
+
+/// A payment that passed verification
+pub struct VerifiedPayment {
+    /// Transaction signed by the client, still missing the fee payer signature
+    pub transaction: Transaction,
+    /// Signature the transaction will have once the facilitator signs it
+    pub signature: Signature,
+    /// Token authority that signed the transfer
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub decimals: u8,
+    /// Nonce from the payment's memo instruction, if any
+    pub nonce: Option<String>,
+}
+
+/// Local x402 facilitator settling "exact" payments with `FACILITATOR_KEYPAIR`
+pub struct Facilitator<C = RetryingRpc<RpcClient>> {
+    client: C,
+    confirmer: Confirmer,
+    keypair: Keypair,
+    network: String,
+    ledger: Mutex<Ledger>,
+}
+
+impl Facilitator {
+    pub fn new(
+        cluster: &ClusterProfile,
+        confirmer: Confirmer,
+        retry: RetryPolicy,
+        keypair: Keypair,
+        ledger: Ledger,
+    ) -> Self {
+        Self::with_client(
+            RetryingRpc::new(cluster.client(), retry),
+            &cluster.network,
+            confirmer,
+            keypair,
+            ledger,
+        )
+    }
+}
+
+impl<C: RpcApi> Facilitator<C> {
+    /// Settle payments for `network` through `client`
+    pub fn with_client(
+        client: C,
+        network: &str,
+        confirmer: Confirmer,
+        keypair: Keypair,
+        ledger: Ledger,
+    ) -> Self {
+        Self {
+            client,
+            confirmer,
+            keypair,
+            network: network.to_string(),
+            ledger: Mutex::new(ledger),
+        }
+    }
+
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    pub fn supported(&self) -> SupportedResponse {
+        SupportedResponse {
+            kinds: vec![SupportedKind {
+                x402_version: X402_VERSION,
+                scheme: SCHEME_EXACT.to_string(),
+                network: self.network.clone(),
+                extra: Some(json!({ "feePayer": self.pubkey().to_string() })),
+            }],
+        }
+    }
+
+    /// Check that the payload is a well-formed transfer matching the
+    /// requirements
+    pub async fn verify(
+        &self,
+        request: &FacilitatorRequest,
+    ) -> Result<VerifiedPayment, VerifyError> {
+        let payment = self.inspect(request)?;
+        check_replay(&*self.ledger.lock().await, &payment)?;
+        self.check_on_chain(&payment.transaction).await?;
+        Ok(payment)
+    }
+
+    /// Check the payload against the requirements, without the ledger or
+    /// the cluster
+    fn inspect(
+        &self,
+        request: &FacilitatorRequest,
+    ) -> Result<VerifiedPayment, VerifyError> {
+        request.validate()?;
+        let requirements = &request.payment_requirements;
+        if requirements.network != self.network {
+            return Err(VerifyError::NetworkMismatch(
+                requirements.network.clone(),
+                self.network.clone(),
+            ));
+        }
+
+        let transaction = request.payment_payload.transaction()?;
+        // Every account index is in range from here on
+        transaction
+            .sanitize()
+            .map_err(|err| invalid_transaction(err.to_string()))?;
+        let message = &transaction.message;
+        let account_key = |key_index: u8| {
+            message.account_keys.get(key_index as usize).copied().ok_or_else(
+                || {
+                    invalid_transaction(format!(
+                        "account index {} is out of range",
+                        key_index
+                    ))
+                },
+            )
+        };
+
+        // The facilitator sponsors the fee, so it must be the fee payer and
+        // nothing else: no instruction may touch its account, except to fund
+        // the recipient's token account, checked below
+        let fee_payer =
+            message.account_keys.first().copied().unwrap_or_default();
+        if fee_payer != self.pubkey() {
+            return Err(VerifyError::FeePayerMismatch(fee_payer));
+        }
+        for (index, instruction) in message.instructions.iter().enumerate() {
+            if instruction.accounts.contains(&0)
+                && account_key(instruction.program_id_index)?
+                    != spl_associated_token_account_interface::program::id()
+            {
+                return Err(VerifyError::FeePayerInInstruction(index));
+            }
+        }
+        verify_client_signatures(&transaction)?;
+
+        let asset = requirements.asset()?;
+        let pay_to = requirements.pay_to()?;
+        let required_amount = requirements.amount()?;
+
+        let mut transfers = Vec::new();
+        let mut nonce = None;
+        let (mut unit_limit, mut unit_price) = (None, None);
+
+        for (index, instruction) in message.instructions.iter().enumerate() {
+            let program_id = account_key(instruction.program_id_index)?;
+            let account =
+                |position: usize| match instruction.accounts.get(position) {
+                    Some(&key_index) => account_key(key_index),
+                    None => Err(VerifyError::UnexpectedInstruction(
+                        index, program_id,
+                    )),
+                };
+
+            if program_id == solana_compute_budget_interface::id() {
+                // The facilitator pays any priority fee, so only a capped
+                // limit and price are accepted, each at most once, and a
+                // price only with a limit
+                let budget = if instruction.accounts.is_empty() {
+                    ComputeBudget::parse(&instruction.data)
+                } else {
+                    None
+                };
+                let within_caps = match budget {
+                    Some(ComputeBudget::UnitLimit(units)) => {
+                        unit_limit.replace(units).is_none()
+                            && units <= MAX_COMPUTE_UNIT_LIMIT
+                    }
+                    Some(ComputeBudget::UnitPrice(price)) => {
+                        unit_price.replace((index, price)).is_none()
+                            && price <= MAX_COMPUTE_UNIT_PRICE
+                    }
+                    None => false,
+                };
+                if !within_caps {
+                    return Err(VerifyError::ComputeBudget(index));
+                }
+                continue;
+            }
+
+            if program_id == MEMO_PROGRAM_ID {
+                // At most one short memo, carrying the payment nonce
+                let memo = std::str::from_utf8(&instruction.data).ok().filter(
+                    |memo| !memo.is_empty() && memo.len() <= MAX_NONCE_LEN,
+                );
+                match memo {
+                    Some(memo)
+                        if nonce.is_none()
+                            && instruction.accounts.is_empty() =>
+                    {
+                        nonce = Some(memo.to_string());
+                    }
+                    _ => return Err(VerifyError::InvalidMemo(index)),
+                }
+                continue;
+            }
+
+            if program_id
+                == spl_associated_token_account_interface::program::id()
+            {
+                // Only allow idempotently creating the recipient's token
+                // account for the asset, funded by the fee payer
+                let recipient_account =
+                    match TokenProgram::from_owner(&account(5)?) {
+                        Some(program) => token::associated_token_address(
+                            &pay_to, &asset, program,
+                        ),
+                        None => Pubkey::default(),
+                    };
+                if instruction.data != [ATA_CREATE_IDEMPOTENT]
+                    || instruction.accounts.len() != 6
+                    || instruction.accounts[0] != 0
+                    || instruction.accounts[1..].contains(&0)
+                    || account(1)? != recipient_account
+                    || account(2)? != pay_to
+                    || account(3)? != asset
+                    || account(4)? != solana_system_interface::program::id()
+                {
+                    return Err(VerifyError::UnexpectedInstruction(
+                        index, program_id,
+                    ));
+                }
+                continue;
+            }
+
+            let Some(program) = TokenProgram::from_owner(&program_id) else {
+                return Err(VerifyError::UnexpectedInstruction(
+                    index, program_id,
+                ));
+            };
+            match TokenInstruction::unpack(&instruction.data) {
+                Ok(TokenInstruction::TransferChecked { amount, decimals }) => {
+                    transfers.push((
+                        program,
+                        account(1)?,
+                        account(2)?,
+                        account(3)?,
+                        amount,
+                        decimals,
+                    ));
+                }
+                _ => {
+                    return Err(VerifyError::UnexpectedInstruction(
+                        index, program_id,
+                    ))
+                }
+            }
+        }
+
+        // Without a limit, the runtime grants each instruction 200,000 units
+        if let (None, Some((index, price))) = (unit_limit, unit_price) {
+            if price > 0 {
+                return Err(VerifyError::ComputeBudget(index));
+            }
+        }
+
+        let &[(program, mint, destination, authority, amount, decimals)] =
+            transfers.as_slice()
+        else {
+            return Err(VerifyError::TransferCount(transfers.len()));
+        };
+        if mint != asset {
+            return Err(VerifyError::MintMismatch(mint));
+        }
+        if destination
+            != token::associated_token_address(&pay_to, &asset, program)
+        {
+            return Err(VerifyError::RecipientMismatch(destination));
+        }
+        if amount != required_amount {
+            return Err(VerifyError::AmountMismatch(amount, required_amount));
+        }
+
+        // Signing is deterministic, so this is the signature the settled
+        // transaction will have: a replayed payload maps to the same entry
+        let signature = self.keypair.sign_message(&message.serialize());
+
+        Ok(VerifiedPayment {
+            transaction,
+            signature,
+            payer: authority,
+            amount,
+            decimals,
+            nonce,
+        })
+    }
+
+    /// Check that the blockhash is still valid and the transaction would
+    /// succeed
+    async fn check_on_chain(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<(), VerifyError> {
+        let blockhash = &transaction.message.recent_blockhash;
+        if !self.client.is_blockhash_valid(blockhash).await? {
+            return Err(VerifyError::BlockhashExpired);
+        }
+
+        // The fee payer signature is only added at settlement, so the
+        // simulation doesn't check signatures
+        if let Some(err) =
+            self.client.simulate_transaction(transaction).await?
+        {
+            return Err(VerifyError::SimulationFailed(err.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check the payload and reserve it in the ledger, so it can't be
+    /// settled twice while it is in flight
+    async fn reserve(
+        &self,
+        request: &FacilitatorRequest,
+    ) -> Result<VerifiedPayment, VerifyError> {
+        let payment = self.inspect(request)?;
+        let mut ledger = self.ledger.lock().await;
+        check_replay(&ledger, &payment)?;
+        ledger
+            .reserve(&payment.signature.to_string(), payment.nonce.as_deref());
+        Ok(payment)
+    }
+
+    /// Add the fee payer signature, then submit and confirm the transaction
+    async fn submit(&self, mut transaction: Transaction) -> Result<Signature> {
+        let recent_blockhash = transaction.message.recent_blockhash;
+        transaction.try_partial_sign(&[&self.keypair], recent_blockhash)?;
+        // The client's signature pins the blockhash, so an expired payment
+        // can't be re-signed
+        self.confirmer.submit(&self.client, &transaction).await
+    }
+
+    /// Verify, then submit and confirm the payment transaction and record it
+    /// in the ledger. The payment is reserved while it settles, so a payload
+    /// can't race itself, but the ledger stays unlocked during RPC calls.
+    pub async fn settle(
+        &self,
+        request: &FacilitatorRequest,
+        reporter: &dyn Reporter,
+    ) -> SettlementResponse {
+        let requirements = &request.payment_requirements;
+        let network = requirements.network.clone();
+        let payment = match self.reserve(request).await {
+            Ok(payment) => payment,
+            Err(err) => {
+                return SettlementResponse {
+                    success: false,
+                    error_reason: Some(format!("{}: {}", err.code(), err)),
+                    transaction: String::new(),
+                    network,
+                    payer: None,
+                };
+            }
+        };
+
+        let submitted = match self.check_on_chain(&payment.transaction).await {
+            Ok(()) => self
+                .submit(payment.transaction.clone())
+                .await
+                .map_err(|err| format!("settlement_failed: {}", err)),
+            Err(err) => Err(format!("{}: {}", err.code(), err)),
+        };
+
+        let mut ledger = self.ledger.lock().await;
+        let signature = match submitted {
+            Ok(signature) => signature,
+            Err(reason) => {
+                ledger.release(
+                    &payment.signature.to_string(),
+                    payment.nonce.as_deref(),
+                );
+                return SettlementResponse {
+                    success: false,
+                    error_reason: Some(reason),
+                    transaction: String::new(),
+                    network,
+                    payer: Some(payment.payer.to_string()),
+                };
+            }
+        };
+
+        let entry = LedgerEntry {
+            signature: signature.to_string(),
+            nonce: payment.nonce,
+            payer: payment.payer.to_string(),
+            pay_to: requirements.pay_to.clone(),
+            asset: requirements.asset.clone(),
+            amount: payment.amount,
+            decimals: payment.decimals,
+            resource: requirements.resource.clone(),
+            network: network.clone(),
+            settled_at: LedgerEntry::now(),
+        };
+        // The payment went through, so report success even if it can't be
+        // recorded
+        if let Err(err) = ledger.record(entry) {
+            reporter.warning(&format!(
+                "Failed to record settlement {}: {:#}",
+                signature, err
+            ));
+        }
+        SettlementResponse {
+            success: true,
+            error_reason: None,
+            transaction: signature.to_string(),
+            network,
+            payer: Some(payment.payer.to_string()),
+        }
+    }
+}
+
+/// Reject a payload or nonce that is settled or being settled
+fn check_replay(
+    ledger: &Ledger,
+    payment: &VerifiedPayment,
+) -> Result<(), VerifyError> {
+    let signature = payment.signature.to_string();
+    if ledger.contains_signature(&signature) {
+        return Err(VerifyError::DuplicatePayment(signature));
+    }
+    if let Some(nonce) =
+        payment.nonce.as_deref().filter(|nonce| ledger.contains_nonce(nonce))
+    {
+        return Err(VerifyError::DuplicatePayment(format!("nonce {}", nonce)));
+    }
+    Ok(())
+}
+
+/// Check every signature except the fee payer's, which the facilitator adds
+fn verify_client_signatures(
+    transaction: &Transaction,
+) -> Result<(), VerifyError> {
+    let message = &transaction.message;
+    let required = message.header.num_required_signatures as usize;
+    if message.account_keys.len() < required {
+        return Err(invalid_transaction(format!(
+            "{} signers required but only {} accounts",
+            required,
+            message.account_keys.len()
+        )));
+    }
+    if required < 2 || transaction.signatures.len() != required {
+        return Err(VerifyError::InvalidSignature);
+    }
+
+    let message_data = message.serialize();
+    let all_valid =
+        transaction.signatures.iter().zip(&message.account_keys).skip(1).all(
+            |(signature, pubkey)| {
+                signature.verify(pubkey.as_ref(), &message_data)
+            },
+        );
+    if all_valid {
+        Ok(())
+    } else {
+        Err(VerifyError::InvalidSignature)
+    }
+}
+
+fn invalid_transaction(reason: impl Into<String>) -> VerifyError {
+    VerifyError::Payload(X402Error::InvalidField {
+        field: "payload.transaction",
+        reason: reason.into(),
+    })
+}
+
+/// Serve `/verify`, `/settle` and `/supported` on `bind`, recording
+/// settlements in the ledger at `ledger_path` within the project
+pub async fn run(
+    project: &Project,
+    bind: &str,
+    cluster: &ClusterProfile,
+    confirmer: Confirmer,
+    retry: RetryPolicy,
+    ledger_path: &Path,
+    reporter: Arc<dyn Reporter>,
+) -> Result<()> {
+    project.load_env()?;
+
+    let keypair = load_keypair("FACILITATOR_KEYPAIR")?;
+    let ledger_path = project.path(ledger_path);
+    let ledger = Ledger::open(&ledger_path)?;
+    let settled = ledger.entries().len();
+    let facilitator =
+        Arc::new(Facilitator::new(cluster, confirmer, retry, keypair, ledger));
+    let state = FacilitatorState {
+        facilitator: facilitator.clone(),
+        reporter: reporter.clone(),
+    };
+
+    let app = Router::new()
+        .route("/verify", post(verify_handler))
+        .route("/settle", post(settle_handler))
+        .route("/supported", get(supported_handler))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(bind)
+        .await
+        .context(format!("Failed to bind facilitator to {}", bind))?;
+
+    reporter.step_finished(&format!(
+        "Facilitator {} listening on http://{} ({})",
+        truncate_pubkey(&facilitator.pubkey().to_string()),
+        bind,
+        facilitator.network
+    ));
+    reporter.info(&format!(
+        "Ledger: {} ({} settled payments)",
+        ledger_path.display(),
+        settled
+    ));
+    reporter.result(json!({
+        "command": "facilitator serve",
+        "result": "listening",
+        "address": bind,
+        "facilitator": facilitator.pubkey().to_string(),
+        "network": facilitator.network,
+        "ledger": ledger_path.display().to_string(),
+        "settled_payments": settled,
+    }));
+
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// List the settlements recorded in the ledger at `ledger_path` within the
+/// project
+pub async fn ledger(
+    project: &Project,
+    ledger_path: &Path,
+    reporter: &dyn Reporter,
+) -> Result<()> {
+    let ledger_path = project.path(ledger_path);
+    let ledger = Ledger::open(&ledger_path)?;
+    let entries = ledger.entries();
+
+    if entries.is_empty() {
+        reporter.info(&format!(
+            "No settlements recorded in {}",
+            ledger_path.display()
+        ));
+    } else {
+        reporter.step_finished(&format!(
+            "{} settlement(s) in {}",
+            entries.len(),
+            ledger_path.display()
+        ));
+        for entry in entries {
+            reporter.note(&format!(
+                "{}  {} of {} from {} to {}  {}  Tx: {}",
+                entry.settled_at,
+                token::to_ui_amount(entry.amount, entry.decimals),
+                truncate_pubkey(&entry.asset),
+                truncate_pubkey(&entry.payer),
+                truncate_pubkey(&entry.pay_to),
+                entry.resource,
+                entry.signature
+            ));
+        }
+    }
+    reporter.result(json!({
+        "command": "facilitator ledger",
+        "result": "success",
+        "ledger": ledger_path.display().to_string(),
+        "settlements": entries,
+    }));
+    Ok(())
+}
+
+/// Shared state of the facilitator's HTTP handlers
+#[derive(Clone)]
+struct FacilitatorState {
+    facilitator: Arc<Facilitator>,
+    reporter: Arc<dyn Reporter>,
+}
+
+async fn supported_handler(
+    State(state): State<FacilitatorState>,
+) -> Json<SupportedResponse> {
+    Json(state.facilitator.supported())
+}
+
+async fn verify_handler(
+    State(state): State<FacilitatorState>,
+    Json(request): Json<FacilitatorRequest>,
+) -> Json<VerifyResponse> {
+    let response = match state.facilitator.verify(&request).await {
+        Ok(payment) => VerifyResponse {
+            is_valid: true,
+            invalid_reason: None,
+            message: None,
+            payer: Some(payment.payer.to_string()),
+        },
+        Err(err) => {
+            state.reporter.info(&format!("Rejected payment: {}", err));
+            VerifyResponse {
+                is_valid: false,
+                invalid_reason: Some(err.code().to_string()),
+                message: Some(err.to_string()),
+                payer: None,
+            }
+        }
+    };
+    Json(response)
+}
+
+async fn settle_handler(
+    State(state): State<FacilitatorState>,
+    Json(request): Json<FacilitatorRequest>,
+) -> Json<SettlementResponse> {
+    let response =
+        state.facilitator.settle(&request, state.reporter.as_ref()).await;
+    if response.success {
+        state.reporter.step_finished(&format!(
+            "Settled payment. Tx: {}",
+            response.transaction
+        ));
+    } else {
+        state.reporter.info(&format!(
+            "Settlement failed: {}",
+            response.error_reason.as_deref().unwrap_or_default()
+        ));
+    }
+    Json(response)
+}
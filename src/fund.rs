@@ -1,165 +1,697 @@
-use solana_client::rpc_client::RpcClient;
+use crate::cluster::ClusterProfile;
+use crate::confirm::{Confirmation, Confirmer, MAX_RESUBMITS};
+use crate::error::Error;
+use crate::keystore::load_keypair;
+use crate::project::Project;
+use crate::report::{self, Reporter};
+use crate::retry::{self, RetryPolicy, RetryingRpc};
+use crate::rpc::RpcApi;
+use crate::token::{
+    self, MetadataFields, MintInfo, MintSpec, TokenProgram, TransferFee,
+};
+use crate::utils::env_file::EnvFile;
+use crate::utils::truncate_pubkey;
+use anyhow::{Context, Result};
+use serde::Serialize;
 use solana_sdk::{
+    native_token::LAMPORTS_PER_SOL,
     pubkey::Pubkey,
     signature::{Keypair, Signer},
-    native_token::LAMPORTS_PER_SOL,
     transaction::Transaction,
-    program_pack::Pack,
 };
-use solana_system_interface::instruction as system_instruction;
-use solana_commitment_config::CommitmentConfig;
-use spl_token::state::Mint;
-#[allow(deprecated)]
-use spl_token::instruction as token_instruction;
-use std::time::Duration;
-use tokio::time::sleep;
-use anyhow::{Result, Context};
-use serde_json::json;
-use crate::utils::{log_action, log_info, load_keypair_from_env, output_json, truncate_pubkey};
+use std::env;
+use std::str::FromStr;
+
+/// Legacy file older versions stored `XUSD_MINT` in
+const LEGACY_MINT_FILE: &str = ".env.mint";
+
+/// Number of decimals used by the xUSD test mint
+pub const XUSD_DECIMALS: u8 = 6;
+
+/// Default xUSD balance the Payer is topped up to by `xforth fund`
+pub const DEFAULT_XUSD_AMOUNT: u64 = 1000;
 
-pub async fn run(rpc_url: &str, json_output: bool) -> Result<()> {
-    // Load .env from current directory
-    dotenvy::dotenv().context("Failed to load .env file. Make sure you're in the project directory and have run 'xforth init' first.")?;
+/// Default SOL balance each wallet is topped up to by `xforth fund`
+pub const DEFAULT_TARGET_SOL: f64 = 0.5;
 
-    if !json_output {
-        log_info("Funding test wallets...");
+/// Default SOL balance below which `xforth fund` airdrops. It leaves room for
+/// the fees and rent a run spends, so the next run doesn't airdrop again.
+pub const DEFAULT_MIN_SOL: f64 = 0.25;
+
+/// Default Token-2022 transfer fee, in basis points
+pub const DEFAULT_FEE_BASIS_POINTS: u16 = 50;
+
+/// Default Token-2022 maximum transfer fee, in whole xUSD
+pub const DEFAULT_MAX_FEE: u64 = 5;
+
+/// Options for `xforth fund`
+#[derive(Clone, Debug)]
+pub struct FundOptions {
+    /// xUSD balance the Payer is topped up to
+    pub xusd_amount: u64,
+    /// SOL balance each wallet is topped up to
+    pub target_sol: f64,
+    /// SOL balance below which a wallet is topped up
+    pub min_sol: f64,
+    /// Token program used when a new xUSD mint is created
+    pub token_program: TokenProgram,
+    /// Transfer fee in basis points (Token-2022 only)
+    pub fee_basis_points: u16,
+    /// Maximum transfer fee in whole xUSD (Token-2022 only)
+    pub max_fee: u64,
+    /// On-mint metadata (Token-2022 only)
+    pub metadata: MetadataFields,
+    /// Whether SOL may be airdropped; wallets short of `min_sol` are an
+    /// error otherwise
+    pub airdrop: bool,
+}
+
+impl Default for FundOptions {
+    fn default() -> Self {
+        Self {
+            xusd_amount: DEFAULT_XUSD_AMOUNT,
+            target_sol: DEFAULT_TARGET_SOL,
+            min_sol: DEFAULT_MIN_SOL,
+            token_program: TokenProgram::default(),
+            fee_basis_points: DEFAULT_FEE_BASIS_POINTS,
+            max_fee: DEFAULT_MAX_FEE,
+            metadata: MetadataFields {
+                name: "xForth Test USD".to_string(),
+                symbol: "xUSD".to_string(),
+                uri: String::new(),
+            },
+            airdrop: true,
+        }
+    }
+}
+
+impl FundOptions {
+    /// Transfer fee of a new mint: Token-2022 mints carry one, SPL Token mints
+    /// cannot
+    pub fn transfer_fee(&self) -> Result<Option<TransferFee>> {
+        match self.token_program {
+            TokenProgram::SplToken => Ok(None),
+            TokenProgram::Token2022 => Ok(Some(TransferFee {
+                basis_points: self.fee_basis_points,
+                maximum_fee: token::to_base_units(self.max_fee, XUSD_DECIMALS)
+                    .context("Maximum transfer fee is too large")?,
+            })),
+        }
     }
 
-    let client = RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed());
+    /// On-mint metadata of a new mint (Token-2022 only)
+    pub fn mint_metadata(&self) -> Option<&MetadataFields> {
+        match self.token_program {
+            TokenProgram::SplToken => None,
+            TokenProgram::Token2022 => Some(&self.metadata),
+        }
+    }
+}
+
+/// Outcome of `xforth fund`
+#[derive(Clone, Debug, Serialize)]
+pub struct FundReport {
+    pub payer_pubkey: String,
+    pub facilitator_pubkey: String,
+    /// `None` when the wallet already held the target SOL balance
+    pub payer_airdrop_tx: Option<String>,
+    pub facilitator_airdrop_tx: Option<String>,
+    pub mint_pubkey: String,
+    pub mint_created: bool,
+    pub mint_tx: Option<String>,
+    pub token_program: String,
+    pub transfer_fee: Option<TransferFee>,
+    pub payer_token_account: String,
+    pub facilitator_token_account: String,
+    /// xUSD amounts are in whole tokens
+    pub xusd_minted: f64,
+    pub xusd_minted_tx: Option<String>,
+    pub payer_xusd_balance: f64,
+}
+
+/// Result of topping up the Payer's xUSD balance
+struct TopUp {
+    payer_token_account: Pubkey,
+    facilitator_token_account: Pubkey,
+    minted: u64,
+    balance: u64,
+    signature: Option<String>,
+}
 
-    let payer_keypair = load_keypair_from_env("PAYER_KEYPAIR")?;
-    let facilitator_keypair = load_keypair_from_env("FACILITATOR_KEYPAIR")?;
+pub async fn run(
+    project: &Project,
+    cluster: &ClusterProfile,
+    confirmer: &Confirmer,
+    retry: &RetryPolicy,
+    options: &FundOptions,
+    reporter: &dyn Reporter,
+) -> Result<FundReport> {
+    project.load_env()?;
+    // Mints created by older versions were stored separately
+    let legacy_mint_file = project.path(LEGACY_MINT_FILE);
+    dotenvy::from_path(&legacy_mint_file).ok();
 
-    // Airdrop to Payer
-    let payer_tx = airdrop_with_retry(&client, &payer_keypair.pubkey(), "Payer", json_output).await?;
+    let client = RetryingRpc::new(cluster.client(), *retry);
 
-    // Airdrop to Facilitator
-    let facilitator_tx = airdrop_with_retry(&client, &facilitator_keypair.pubkey(), "Facilitator", json_output).await?;
+    let payer_keypair = load_keypair("PAYER_KEYPAIR")?;
+    let facilitator_keypair = load_keypair("FACILITATOR_KEYPAIR")?;
+
+    let mut env_file = project.env_file()?;
+    if env_file.get("XUSD_MINT").is_none() {
+        if let Ok(mint) = env::var("XUSD_MINT") {
+            env_file.set("XUSD_MINT", mint.trim());
+            env_file.save()?;
+        }
+    }
+
+    // Airdrops are refused up front on clusters without a faucet
+    let options = FundOptions {
+        airdrop: options.airdrop && cluster.faucet,
+        ..options.clone()
+    };
+    let report = run_with(
+        &client,
+        confirmer,
+        &payer_keypair,
+        &facilitator_keypair,
+        &mut env_file,
+        &options,
+        reporter,
+    )
+    .await?;
+
+    // The mint now lives in .env
+    match std::fs::remove_file(&legacy_mint_file) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(err).context(format!(
+                "Failed to remove {}",
+                legacy_mint_file.display()
+            ))
+        }
+    }
+
+    let transactions = [
+        &report.payer_airdrop_tx,
+        &report.facilitator_airdrop_tx,
+        &report.mint_tx,
+        &report.xusd_minted_tx,
+    ];
+    for link in transactions
+        .into_iter()
+        .flatten()
+        .filter_map(|sig| cluster.explorer_link(sig))
+    {
+        reporter.info(&format!("Explorer: {}", link));
+    }
+    reporter.result(report::success("fund", &report));
+
+    Ok(report)
+}
+
+/// Fund the wallets through `client`, reusing or recording `XUSD_MINT` in
+/// `env_file`. Unlike [`run`], it does not report the result.
+pub async fn run_with(
+    client: &impl RpcApi,
+    confirmer: &Confirmer,
+    payer_keypair: &Keypair,
+    facilitator_keypair: &Keypair,
+    env_file: &mut EnvFile,
+    options: &FundOptions,
+    reporter: &dyn Reporter,
+) -> Result<FundReport> {
+    reporter.info("Funding test wallets...");
+
+    // Airdrop to Payer and Facilitator concurrently
+    let (payer_pubkey, facilitator_pubkey) =
+        (payer_keypair.pubkey(), facilitator_keypair.pubkey());
+    let (payer_tx, facilitator_tx) = tokio::try_join!(
+        ensure_sol_balance(
+            client,
+            confirmer,
+            &payer_pubkey,
+            "Payer",
+            options,
+            reporter
+        ),
+        ensure_sol_balance(
+            client,
+            confirmer,
+            &facilitator_pubkey,
+            "Facilitator",
+            options,
+            reporter
+        ),
+    )?;
+
+    // Reuse the project's xUSD mint when it is still valid, otherwise create
+    // one
+    let mint_address = match env_file.get("XUSD_MINT") {
+        Some(value) => Some(Pubkey::from_str(value).map_err(|_| {
+            Error::InvalidInput(format!(
+                "XUSD_MINT is not a valid public key: {}",
+                value
+            ))
+        })?),
+        None => None,
+    };
+    let existing = match mint_address {
+        Some(address) => {
+            existing_mint(client, &address, &payer_keypair.pubkey(), reporter)
+                .await?
+        }
+        None => None,
+    };
+    let (mint, mint_tx) = match existing {
+        Some(mint) => (mint, None),
+        None => {
+            let (mint, sig) = create_mint(
+                client,
+                confirmer,
+                payer_keypair,
+                &facilitator_keypair.pubkey(),
+                options,
+                reporter,
+            )
+            .await?;
+            (mint, Some(sig))
+        }
+    };
+
+    save_mint(env_file, &mint.address)?;
+
+    if mint.program != options.token_program && mint_tx.is_none() {
+        reporter.info(&format!(
+            "Reusing existing {} mint; --token-program {} only applies to \
+                new mints",
+            mint.program.name(),
+            options.token_program.name()
+        ));
+    }
 
     // Mint test tokens
-    let mint_pubkey = mint_test_tokens(&client, &payer_keypair, json_output).await?;
+    let top_up = top_up_tokens(
+        client,
+        confirmer,
+        payer_keypair,
+        &facilitator_keypair.pubkey(),
+        &mint,
+        options.xusd_amount,
+        reporter,
+    )
+    .await?;
+
+    let report = FundReport {
+        payer_pubkey: payer_keypair.pubkey().to_string(),
+        facilitator_pubkey: facilitator_keypair.pubkey().to_string(),
+        payer_airdrop_tx: payer_tx,
+        facilitator_airdrop_tx: facilitator_tx,
+        mint_pubkey: mint.address.to_string(),
+        mint_created: mint_tx.is_some(),
+        mint_tx,
+        token_program: mint.program.name().to_string(),
+        transfer_fee: mint.transfer_fee,
+        payer_token_account: top_up.payer_token_account.to_string(),
+        facilitator_token_account: top_up
+            .facilitator_token_account
+            .to_string(),
+        xusd_minted: token::to_ui_amount(top_up.minted, mint.decimals),
+        xusd_minted_tx: top_up.signature,
+        payer_xusd_balance: token::to_ui_amount(top_up.balance, mint.decimals),
+    };
+
+    Ok(report)
+}
 
-    if json_output {
-        output_json(&json!({
-            "command": "fund",
-            "result": "success",
-            "payer_airdrop_tx": payer_tx,
-            "facilitator_airdrop_tx": facilitator_tx,
-            "mint_pubkey": mint_pubkey.to_string(),
-            "xusd_minted": 1000,
-        }));
+/// Airdrop enough SOL to bring `pubkey` up to `target_sol` once it has fallen
+/// below `min_sol`.
+///
+/// Returns the airdrop signature, or `None` when the wallet is already funded.
+async fn ensure_sol_balance(
+    client: &impl RpcApi,
+    confirmer: &Confirmer,
+    pubkey: &Pubkey,
+    label: &str,
+    options: &FundOptions,
+    reporter: &dyn Reporter,
+) -> Result<Option<String>> {
+    let target_lamports =
+        (options.target_sol * LAMPORTS_PER_SOL as f64) as u64;
+    let min_lamports = (options.min_sol * LAMPORTS_PER_SOL as f64) as u64;
+    let balance = client.get_balance(pubkey).await?;
+    if balance >= min_lamports.min(target_lamports) {
+        reporter.step_finished(&format!(
+            "{} already funded with {} SOL, skipping airdrop",
+            label,
+            balance as f64 / LAMPORTS_PER_SOL as f64
+        ));
+        return Ok(None);
+    }
+    if !options.airdrop {
+        return Err(Error::AirdropUnsupported(format!(
+            "{} holds {} SOL, below the {} SOL minimum, and the cluster has \
+                no faucet. Transfer SOL to {} instead.",
+            label,
+            balance as f64 / LAMPORTS_PER_SOL as f64,
+            min_lamports as f64 / LAMPORTS_PER_SOL as f64,
+            pubkey
+        ))
+        .into());
     }
 
-    Ok(())
+    let sig = airdrop(
+        client,
+        confirmer,
+        pubkey,
+        label,
+        target_lamports - balance,
+        reporter,
+    )
+    .await?;
+    Ok(Some(sig))
 }
 
-async fn airdrop_with_retry(
-    client: &RpcClient,
+/// Request an airdrop and wait for it, requesting a new one if it expires
+/// unconfirmed
+async fn airdrop(
+    client: &impl RpcApi,
+    confirmer: &Confirmer,
     pubkey: &Pubkey,
     label: &str,
-    json_output: bool,
+    amount_lamports: u64,
+    reporter: &dyn Reporter,
 ) -> Result<String> {
-    let amount_sol = 0.5;
-    let amount_lamports = (amount_sol * LAMPORTS_PER_SOL as f64) as u64;
-
-    if !json_output {
-        log_action(&format!("Airdropping {} SOL to {}...", amount_sol, label));
-    }
-
-    let mut last_error = None;
-
-    for attempt in 1..=5 {
-        match client.request_airdrop(pubkey, amount_lamports) {
-            Ok(sig) => {
-                // Wait for confirmation
-                for _ in 0..30 {
-                    match client.get_signature_status(&sig) {
-                        Ok(Some(Ok(_))) => {
-                            if !json_output {
-                                log_action(&format!("{} funded: {} Tx: {}", label, truncate_pubkey(&pubkey.to_string()), sig));
-                            }
-                            return Ok(sig.to_string());
-                        }
-                        Ok(Some(Err(e))) => {
-                            return Err(anyhow::anyhow!("Transaction failed: {:?}", e));
-                        }
-                        _ => {
-                            sleep(Duration::from_millis(1000)).await;
-                        }
-                    }
-                }
-                return Err(anyhow::anyhow!("Transaction confirmation timeout"));
+    let amount_sol = amount_lamports as f64 / LAMPORTS_PER_SOL as f64;
+
+    reporter.step_started(&format!(
+        "Airdropping {} SOL to {}...",
+        amount_sol, label
+    ));
+    let tx_label = format!("{} airdrop", label);
+
+    for _ in 0..=MAX_RESUBMITS {
+        // The faucet signs with a blockhash at least this recent
+        let recent_blockhash = client.get_latest_blockhash().await?;
+        // Rate limits and other transient errors are retried by the client
+        let sig = match client.request_airdrop(pubkey, amount_lamports).await {
+            Ok(sig) => sig,
+            Err(err) if retry::is_rate_limited(&err) => {
+                return Err(Error::AirdropRateLimited(format!(
+                    "{} ({})",
+                    label, err
+                ))
+                .into());
+            }
+            Err(err) => return Err(err).context("Airdrop failed"),
+        };
+        reporter.transaction_sent(&tx_label, &sig);
+
+        match confirmer.confirm(client, &sig, &recent_blockhash).await? {
+            Confirmation::Confirmed => {
+                reporter.transaction_confirmed(&tx_label, &sig);
+                reporter.step_finished(&format!(
+                    "{} funded: {} Tx: {}",
+                    label,
+                    truncate_pubkey(&pubkey.to_string()),
+                    sig
+                ));
+                return Ok(sig.to_string());
             }
-            Err(err) => {
-                last_error = Some(err.to_string());
-                if err.to_string().contains("429") || err.to_string().to_lowercase().contains("too many") {
-                    let delay_ms = 500 * (2_u64.pow(attempt - 1)); // Exponential backoff
-                    if !json_output {
-                        log_info(&format!("Server responded with 429... Retrying after {}ms...", delay_ms));
-                    }
-                    sleep(Duration::from_millis(delay_ms)).await;
-                } else {
-                    return Err(anyhow::anyhow!("Airdrop failed: {}", err));
-                }
+            Confirmation::Failed(e) => {
+                return Err(Error::TransactionFailed(e).into());
+            }
+            Confirmation::Expired => {
+                reporter.warning(&format!(
+                    "Airdrop to {} expired before it \
+                    was confirmed, retrying...",
+                    label
+                ));
             }
         }
     }
 
-    Err(anyhow::anyhow!("Max retries exceeded for airdrop. Last error: {:?}", last_error))
+    Err(Error::TransactionExpired(format!(
+        "Airdrop to {} expired {} times without confirming",
+        label,
+        MAX_RESUBMITS + 1
+    ))
+    .into())
 }
 
-async fn mint_test_tokens(
-    client: &RpcClient,
-    payer: &Keypair,
-    json_output: bool,
-) -> Result<Pubkey> {
-    if !json_output {
-        log_action("Minting 1000 xUSD test tokens to Payer...");
+/// Check that the configured `XUSD_MINT` is still a usable mint controlled by
+/// the Payer.
+///
+/// Returns `None` when the account no longer exists (e.g. after a local
+/// validator reset), so that a fresh mint is created.
+async fn existing_mint(
+    client: &impl RpcApi,
+    address: &Pubkey,
+    payer: &Pubkey,
+    reporter: &dyn Reporter,
+) -> Result<Option<MintInfo>> {
+    let Some(account) = client.get_account(address).await? else {
+        reporter.info(&format!(
+            "Mint {} not found on this cluster, creating a new one",
+            truncate_pubkey(&address.to_string())
+        ));
+        return Ok(None);
+    };
+
+    let mint = MintInfo::from_account(address, &account)?;
+    if mint.mint_authority != Some(*payer) {
+        return Err(Error::InvalidInput(format!(
+            "Mint {} is not controlled by the Payer. Remove XUSD_MINT from \
+                .env to create a new mint.",
+            address
+        ))
+        .into());
     }
 
-    // For v1, we'll create a simple SPL token mint
-    // In production, this would use SPL Token-2022 with fee-on-transfer
+    reporter.step_finished(&format!(
+        "Reusing xUSD mint: {} ({})",
+        truncate_pubkey(&address.to_string()),
+        mint.program.name()
+    ));
+
+    Ok(Some(mint))
+}
+
+async fn create_mint(
+    client: &impl RpcApi,
+    confirmer: &Confirmer,
+    payer: &Keypair,
+    facilitator: &Pubkey,
+    options: &FundOptions,
+    reporter: &dyn Reporter,
+) -> Result<(MintInfo, String)> {
+    let program = options.token_program;
+
+    reporter
+        .step_started(&format!("Creating xUSD mint ({})...", program.name()));
 
     // Create mint keypair
     let mint_keypair = Keypair::new();
     let mint_pubkey = mint_keypair.pubkey();
 
-    let decimals = 6;
-    let mint_rent = client.get_minimum_balance_for_rent_exemption(Mint::LEN)?;
+    // Token-2022 mints carry a transfer fee and on-mint metadata
+    let transfer_fee = options.transfer_fee()?;
+    let metadata = options.mint_metadata();
 
-    // Create mint account
-    let create_mint_ix = system_instruction::create_account(
-        &payer.pubkey(),
-        &mint_pubkey,
-        mint_rent,
-        Mint::LEN as u64,
-        &spl_token::id(),
+    let spec = MintSpec {
+        program,
+        mint: &mint_pubkey,
+        mint_authority: &payer.pubkey(),
+        decimals: XUSD_DECIMALS,
+        transfer_fee,
+        withdraw_authority: facilitator,
+        metadata,
+    };
+    let (_, rent_len) = spec.account_len()?;
+    let mint_rent =
+        client.get_minimum_balance_for_rent_exemption(rent_len).await?;
+    let instructions = spec.instructions(&payer.pubkey(), mint_rent)?;
+
+    let recent_blockhash = client.get_latest_blockhash().await?;
+    let mut transaction = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&payer.pubkey()),
+        &[payer, &mint_keypair],
+        recent_blockhash,
     );
 
-    let init_mint_ix = token_instruction::initialize_mint(
-        &spl_token::id(),
-        &mint_pubkey,
+    let sig = confirmer
+        .send_and_confirm(
+            client,
+            &mut transaction,
+            &[payer, &mint_keypair],
+            "Create mint",
+            reporter,
+        )
+        .await?;
+
+    reporter.step_finished(&format!(
+        "Created xUSD mint: {} Tx: {}",
+        truncate_pubkey(&mint_pubkey.to_string()),
+        sig
+    ));
+    if let Some(fee) = transfer_fee {
+        reporter.info(&format!(
+            "Transfer fee: {} bps (max {} xUSD), withheld fees withdrawable \
+                by Facilitator",
+            fee.basis_points, options.max_fee
+        ));
+    }
+
+    let mint = MintInfo {
+        address: mint_pubkey,
+        program,
+        decimals: XUSD_DECIMALS,
+        mint_authority: Some(payer.pubkey()),
+        transfer_fee,
+    };
+
+    Ok((mint, sig.to_string()))
+}
+
+/// Persist the mint into the project `.env`
+fn save_mint(env_file: &mut EnvFile, mint: &Pubkey) -> Result<()> {
+    if env_file.get("XUSD_MINT") != Some(mint.to_string().as_str()) {
+        env_file.set("XUSD_MINT", &mint.to_string());
+        env_file.save()?;
+    }
+    Ok(())
+}
+
+/// Create missing token accounts and mint xUSD until the Payer holds
+/// `xusd_amount`
+async fn top_up_tokens(
+    client: &impl RpcApi,
+    confirmer: &Confirmer,
+    payer: &Keypair,
+    facilitator: &Pubkey,
+    mint: &MintInfo,
+    xusd_amount: u64,
+    reporter: &dyn Reporter,
+) -> Result<TopUp> {
+    let program = mint.program;
+    let target = token::to_base_units(xusd_amount, mint.decimals)
+        .context("xUSD amount is too large")?;
+
+    let payer_token_account = token::associated_token_address(
         &payer.pubkey(),
-        None,
-        decimals,
-    )?;
+        &mint.address,
+        program,
+    );
+    let facilitator_token_account =
+        token::associated_token_address(facilitator, &mint.address, program);
+
+    let current = token::token_balance(client, &payer_token_account).await?;
+    let facilitator_exists =
+        client.get_account(&facilitator_token_account).await?.is_some();
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let transaction = Transaction::new_signed_with_payer(
-        &[create_mint_ix, init_mint_ix],
+    let to_mint = target.saturating_sub(current);
+    if to_mint == 0 && facilitator_exists {
+        reporter.step_finished(&format!(
+            "Payer already holds {} xUSD, skipping mint",
+            token::to_ui_amount(current, mint.decimals)
+        ));
+        return Ok(TopUp {
+            payer_token_account,
+            facilitator_token_account,
+            minted: 0,
+            balance: current,
+            signature: None,
+        });
+    }
+
+    if to_mint > 0 {
+        reporter.step_started(&format!(
+            "Minting {} xUSD test tokens to Payer...",
+            token::to_ui_amount(to_mint, mint.decimals)
+        ));
+    }
+
+    // Create associated token accounts for both wallets
+    let mut instructions = vec![
+        token::create_associated_token_account_ix(
+            &payer.pubkey(),
+            &payer.pubkey(),
+            &mint.address,
+            program,
+        ),
+        token::create_associated_token_account_ix(
+            &payer.pubkey(),
+            facilitator,
+            &mint.address,
+            program,
+        ),
+    ];
+    if to_mint > 0 {
+        instructions.push(token::mint_to_ix(
+            program,
+            &mint.address,
+            &payer_token_account,
+            &payer.pubkey(),
+            to_mint,
+            mint.decimals,
+        )?);
+    }
+
+    let recent_blockhash = client.get_latest_blockhash().await?;
+    let mut transaction = Transaction::new_signed_with_payer(
+        &instructions,
         Some(&payer.pubkey()),
-        &[payer, &mint_keypair],
+        &[payer],
         recent_blockhash,
     );
 
-    let _sig = client.send_and_confirm_transaction(&transaction)?;
+    let sig = confirmer
+        .send_and_confirm(
+            client,
+            &mut transaction,
+            &[payer],
+            "Mint xUSD",
+            reporter,
+        )
+        .await?;
 
-    if !json_output {
-        log_action(&format!("Minted 1000 xUSD to Payer. Mint: {}", truncate_pubkey(&mint_pubkey.to_string())));
+    // Verify the Payer actually holds the minted tokens before reporting
+    // success
+    let balance = token::token_balance(client, &payer_token_account).await?;
+    if balance < target {
+        return Err(Error::InsufficientBalance(format!(
+            "Payer token balance mismatch: expected {} xUSD, found {}",
+            xusd_amount,
+            token::to_ui_amount(balance, mint.decimals)
+        ))
+        .into());
     }
+    client
+        .get_account(&facilitator_token_account)
+        .await?
+        .context("Facilitator token account was not created")?;
 
-    // Store mint pubkey in environment (append to .env)
-    std::fs::write(".env.mint", format!("XUSD_MINT={}\n", mint_pubkey.to_string()))?;
+    reporter.step_finished(&format!(
+        "Payer holds {} xUSD. Mint: {} Tx: {}",
+        token::to_ui_amount(balance, mint.decimals),
+        truncate_pubkey(&mint.address.to_string()),
+        sig
+    ));
+    reporter.step_finished(&format!(
+        "Payer token account: {}",
+        truncate_pubkey(&payer_token_account.to_string())
+    ));
+    reporter.step_finished(&format!(
+        "Facilitator token account: {}",
+        truncate_pubkey(&facilitator_token_account.to_string())
+    ));
 
-    Ok(mint_pubkey)
-}
\ No newline at end of file
+    Ok(TopUp {
+        payer_token_account,
+        facilitator_token_account,
+        minted: to_mint,
+        balance,
+        signature: Some(sig.to_string()),
+    })
+}
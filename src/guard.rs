@@ -0,0 +1,449 @@
+use crate::confirm::Confirmer;
+use crate::error::Error;
+use crate::report::Reporter;
+use crate::rpc::RpcApi;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    hash::Hash, native_token::LAMPORTS_PER_SOL, signature::Signature,
+    transaction::Transaction,
+};
+use solana_system_interface::instruction::SystemInstruction;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Genesis hash of mainnet-beta
+pub const MAINNET_GENESIS_HASH: &str =
+    "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+
+/// Genesis hash of devnet
+pub const DEVNET_GENESIS_HASH: &str =
+    "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG";
+
+/// Genesis hash of testnet
+pub const TESTNET_GENESIS_HASH: &str =
+    "4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY";
+
+/// Where spending on mainnet is recorded, relative to the project directory
+pub const DEFAULT_SPEND_LOG_PATH: &str = ".xforth/spend.jsonl";
+
+/// Default SOL a single command may spend on mainnet
+pub const DEFAULT_MAX_SPEND_PER_RUN: f64 = 0.05;
+
+/// Default SOL a project may spend on mainnet in [`SPEND_WINDOW_SECS`]
+pub const DEFAULT_MAX_SPEND_PER_DAY: f64 = 0.5;
+
+/// Window the daily cap applies to
+pub const SPEND_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// Compute units each instruction gets when a transaction sets no limit
+const DEFAULT_INSTRUCTION_COMPUTE_UNITS: u64 = 200_000;
+
+/// Most compute units a transaction can use
+const MAX_TRANSACTION_COMPUTE_UNITS: u64 = 1_400_000;
+
+/// Micro-lamports in a lamport, the unit of compute unit prices
+const MICRO_LAMPORTS_PER_LAMPORT: u128 = 1_000_000;
+
+/// Base fee per signature
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Size of the largest token account xforth creates (Token-2022 with the
+/// immutable owner and transfer fee extensions), used to estimate its rent
+const MAX_TOKEN_ACCOUNT_LEN: usize = 182;
+
+/// A cluster as identified by its genesis hash
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    Devnet,
+    Testnet,
+    /// A local validator, a private cluster or the offline VM
+    Unknown,
+}
+
+impl Network {
+    pub fn from_genesis_hash(genesis_hash: &Hash) -> Self {
+        match genesis_hash.to_string().as_str() {
+            MAINNET_GENESIS_HASH => Network::Mainnet,
+            DEVNET_GENESIS_HASH => Network::Devnet,
+            TESTNET_GENESIS_HASH => Network::Testnet,
+            _ => Network::Unknown,
+        }
+    }
+
+    /// The cluster `client` is connected to, whatever its URL says
+    pub async fn detect(client: &impl RpcApi) -> Result<Self> {
+        Ok(Self::from_genesis_hash(&client.get_genesis_hash().await?))
+    }
+}
+
+/// Caps on the lamports spent on mainnet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpendCaps {
+    pub per_run: u64,
+    pub per_day: u64,
+}
+
+impl Default for SpendCaps {
+    fn default() -> Self {
+        Self::from_sol(DEFAULT_MAX_SPEND_PER_RUN, DEFAULT_MAX_SPEND_PER_DAY)
+    }
+}
+
+impl SpendCaps {
+    pub fn from_sol(per_run: f64, per_day: f64) -> Self {
+        Self { per_run: to_lamports(per_run), per_day: to_lamports(per_day) }
+    }
+}
+
+fn to_lamports(sol: f64) -> u64 {
+    (sol * LAMPORTS_PER_SOL as f64) as u64
+}
+
+fn to_sol(lamports: u64) -> f64 {
+    lamports as f64 / LAMPORTS_PER_SOL as f64
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// One transaction paid for on mainnet
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpendEntry {
+    pub signature: String,
+    pub command: String,
+    /// Estimated cost, see [`estimate_cost`]
+    pub lamports: u64,
+    /// Unix timestamp of the confirmation
+    pub spent_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct SpendState {
+    run: u64,
+    entries: Vec<SpendEntry>,
+}
+
+/// Enforces [`SpendCaps`] for one command, recording what it spends in an
+/// append-only JSON Lines log that the daily cap is counted from
+#[derive(Debug)]
+pub struct SpendGuard {
+    path: PathBuf,
+    command: String,
+    caps: SpendCaps,
+    state: Mutex<SpendState>,
+}
+
+impl SpendGuard {
+    /// Open the spend log at `path`, starting empty if it does not exist yet
+    pub fn open(
+        path: impl AsRef<Path>,
+        command: &str,
+        caps: SpendCaps,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err)
+                    .context(format!("Failed to read {}", path.display()))
+            }
+        };
+
+        let mut entries = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: SpendEntry =
+                serde_json::from_str(line).context(format!(
+                    "Invalid entry on line {} of {}",
+                    number + 1,
+                    path.display()
+                ))?;
+            entries.push(entry);
+        }
+
+        Ok(Self {
+            path,
+            command: command.to_string(),
+            caps,
+            state: Mutex::new(SpendState { run: 0, entries }),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, SpendState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn caps(&self) -> SpendCaps {
+        self.caps
+    }
+
+    /// Lamports spent by this command so far
+    pub fn spent_this_run(&self) -> u64 {
+        self.state().run
+    }
+
+    /// Lamports spent in the last [`SPEND_WINDOW_SECS`], by any command
+    pub fn spent_today(&self) -> u64 {
+        let since = now().saturating_sub(SPEND_WINDOW_SECS);
+        self.state()
+            .entries
+            .iter()
+            .filter(|entry| entry.spent_at >= since)
+            .map(|entry| entry.lamports)
+            .sum()
+    }
+
+    /// Fail unless spending `lamports` more stays within both caps
+    pub fn check(&self, lamports: u64) -> Result<()> {
+        let run = self.spent_this_run();
+        if run + lamports > self.caps.per_run {
+            return Err(Error::SpendCapExceeded(format!(
+                "Sending this transaction could spend {} SOL, over the cap \
+                    of {} SOL per run ({} SOL spent so far)",
+                to_sol(lamports),
+                to_sol(self.caps.per_run),
+                to_sol(run)
+            ))
+            .into());
+        }
+        let today = self.spent_today();
+        if today + lamports > self.caps.per_day {
+            return Err(Error::SpendCapExceeded(format!(
+                "Sending this transaction could spend {} SOL, over the cap \
+                    of {} SOL per day ({} SOL spent in the last 24 hours)",
+                to_sol(lamports),
+                to_sol(self.caps.per_day),
+                to_sol(today)
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Record that the transaction `signature` spent `lamports`
+    pub fn record(&self, signature: &Signature, lamports: u64) -> Result<()> {
+        let entry = SpendEntry {
+            signature: signature.to_string(),
+            command: self.command.clone(),
+            lamports,
+            spent_at: now(),
+        };
+
+        if let Some(dir) =
+            self.path.parent().filter(|dir| !dir.as_os_str().is_empty())
+        {
+            fs::create_dir_all(dir)
+                .context(format!("Failed to create {}", dir.display()))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(format!("Failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)
+            .context(format!("Failed to write {}", self.path.display()))?;
+
+        let mut state = self.state();
+        state.run += lamports;
+        state.entries.push(entry);
+        Ok(())
+    }
+}
+
+/// Compute budget program instructions that set what a transaction pays
+pub enum ComputeBudget {
+    UnitLimit(u32),
+    UnitPrice(u64),
+}
+
+impl ComputeBudget {
+    /// Decode `SetComputeUnitLimit` or `SetComputeUnitPrice`
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        match data.split_first()? {
+            (2, units) => Some(Self::UnitLimit(u32::from_le_bytes(
+                units.try_into().ok()?,
+            ))),
+            (3, price) => Some(Self::UnitPrice(u64::from_le_bytes(
+                price.try_into().ok()?,
+            ))),
+            _ => None,
+        }
+    }
+}
+
+/// Fee of `transaction`: the base fee of each required signature and its
+/// priority fee
+pub fn fee(transaction: &Transaction) -> u64 {
+    (transaction.message.header.num_required_signatures as u64
+        * LAMPORTS_PER_SIGNATURE)
+        .saturating_add(priority_fee(transaction))
+}
+
+/// Priority fee of `transaction`: its compute unit price times its compute
+/// unit limit, rounded up to whole lamports
+pub fn priority_fee(transaction: &Transaction) -> u64 {
+    let message = &transaction.message;
+    let (mut unit_limit, mut unit_price, mut instructions) = (None, 0, 0);
+    for instruction in &message.instructions {
+        let program_id =
+            message.account_keys.get(instruction.program_id_index as usize);
+        if program_id != Some(&solana_compute_budget_interface::id()) {
+            instructions += 1;
+            continue;
+        }
+        match ComputeBudget::parse(&instruction.data) {
+            Some(ComputeBudget::UnitLimit(units)) => {
+                unit_limit = Some(units as u64)
+            }
+            Some(ComputeBudget::UnitPrice(price)) => unit_price = price,
+            None => {}
+        }
+    }
+
+    let units = unit_limit
+        .unwrap_or(instructions * DEFAULT_INSTRUCTION_COMPUTE_UNITS)
+        .min(MAX_TRANSACTION_COMPUTE_UNITS);
+    let lamports = (unit_price as u128 * units as u128)
+        .div_ceil(MICRO_LAMPORTS_PER_LAMPORT);
+    u64::try_from(lamports).unwrap_or(u64::MAX)
+}
+
+/// Most SOL `transaction` can cost: its fee, lamports moved by system
+/// program instructions, and rent of associated token accounts it creates
+pub async fn estimate_cost(
+    client: &impl RpcApi,
+    transaction: &Transaction,
+) -> Result<u64> {
+    let message = &transaction.message;
+    let mut lamports = fee(transaction);
+
+    for instruction in &message.instructions {
+        let program_id =
+            message.account_keys[instruction.program_id_index as usize];
+        if program_id == solana_system_interface::program::id() {
+            lamports += match bincode::deserialize(&instruction.data) {
+                Ok(SystemInstruction::Transfer { lamports: amount })
+                | Ok(SystemInstruction::TransferWithSeed {
+                    lamports: amount,
+                    ..
+                })
+                | Ok(SystemInstruction::CreateAccount {
+                    lamports: amount,
+                    ..
+                })
+                | Ok(SystemInstruction::CreateAccountWithSeed {
+                    lamports: amount,
+                    ..
+                }) => amount,
+                _ => 0,
+            };
+        } else if program_id
+            == spl_associated_token_account_interface::program::id()
+        {
+            // Creating an account that already exists costs nothing
+            let Some(&account_index) = instruction.accounts.get(1) else {
+                continue;
+            };
+            let account = message.account_keys[account_index as usize];
+            if client.get_account(&account).await?.is_none() {
+                lamports += client
+                    .get_minimum_balance_for_rent_exemption(
+                        MAX_TOKEN_ACCOUNT_LEN,
+                    )
+                    .await?;
+            }
+        }
+    }
+    Ok(lamports)
+}
+
+/// Settings of the mainnet guard, which every command that moves value
+/// passes before it starts
+#[derive(Clone, Debug, Default)]
+pub struct MainnetGuard {
+    /// `--allow-mainnet`: without it, value-moving commands refuse mainnet
+    pub allow_mainnet: bool,
+    /// `--yes`: skip the confirmation prompt
+    pub assume_yes: bool,
+    pub caps: SpendCaps,
+    pub spend_log: PathBuf,
+}
+
+impl MainnetGuard {
+    /// Check the cluster behind `client` before `command` moves value. Other
+    /// clusters pass as they are; mainnet needs `--allow-mainnet` and a
+    /// confirmation, and `confirmer` then enforces the spend caps.
+    pub async fn check(
+        &self,
+        client: &impl RpcApi,
+        command: &str,
+        confirmer: Confirmer,
+        reporter: &dyn Reporter,
+    ) -> Result<(Network, Confirmer)> {
+        let network = Network::detect(client).await?;
+        if network != Network::Mainnet {
+            return Ok((network, confirmer));
+        }
+        if !self.allow_mainnet {
+            return Err(Error::MainnetNotAllowed(format!(
+                "The RPC endpoint is mainnet-beta, where '{}' moves real \
+                    funds. Pass --allow-mainnet to run it anyway.",
+                command
+            ))
+            .into());
+        }
+
+        let guard = SpendGuard::open(&self.spend_log, command, self.caps)?;
+        reporter.warning(&format!(
+            "'{}' is running on mainnet-beta with real funds. Spending is \
+                capped at {} SOL for this run and {} SOL per day ({} SOL \
+                spent in the last 24 hours).",
+            command,
+            to_sol(self.caps.per_run),
+            to_sol(self.caps.per_day),
+            to_sol(guard.spent_today())
+        ));
+        if !self.assume_yes && !confirm(command)? {
+            return Err(Error::MainnetNotAllowed(
+                "Cancelled: mainnet run was not confirmed".to_string(),
+            )
+            .into());
+        }
+        Ok((network, confirmer.with_spend_guard(Arc::new(guard))))
+    }
+}
+
+/// Ask on the terminal for `mainnet` to be typed. Without a terminal there
+/// is no one to ask, so it fails rather than proceeding.
+fn confirm(command: &str) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Err(Error::MainnetNotAllowed(
+            "Running on mainnet needs a confirmation. Run in a terminal or \
+                pass --yes."
+                .to_string(),
+        )
+        .into());
+    }
+    // On stderr, so that JSON output on stdout stays parseable
+    eprint!("Type 'mainnet' to run '{}' on mainnet-beta: ", command);
+    io::stderr().flush().ok();
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("Failed to read the confirmation")?;
+    Ok(answer.trim() == "mainnet")
+}
@@ -3,11 +3,33 @@ use dioxus::prelude::*;
 
 // Use the library modules instead of redeclaring them
 #[cfg(feature = "gui")]
-use xforth::{init, fund, test};
+use tokio::sync::mpsc::UnboundedReceiver;
+#[cfg(feature = "gui")]
+use xforth::cluster::{Clusters, DEFAULT_CLUSTER};
+#[cfg(feature = "gui")]
+use xforth::config;
+#[cfg(feature = "gui")]
+use xforth::confirm::Confirmer;
+#[cfg(feature = "gui")]
+use xforth::guard::MainnetGuard;
+#[cfg(feature = "gui")]
+use xforth::init::InitOptions;
+#[cfg(feature = "gui")]
+use xforth::keystore;
+#[cfg(feature = "gui")]
+use xforth::project::Project;
+#[cfg(feature = "gui")]
+use xforth::report::{ChannelReporter, Event};
+#[cfg(feature = "gui")]
+use xforth::retry::RetryPolicy;
+#[cfg(feature = "gui")]
+use xforth::{fund, init, test};
 
 #[cfg(not(feature = "gui"))]
 fn main() {
-    eprintln!("Error: xforth-gui must be compiled with the 'gui' feature enabled.");
+    eprintln!(
+        "Error: xforth-gui must be compiled with the 'gui' feature enabled."
+    );
     eprintln!("Run: cargo build --bin xforth-gui --features gui");
     std::process::exit(1);
 }
@@ -17,7 +39,10 @@ fn main() {
 struct AppState {
     project_name: String,
     rpc_url: String,
-    use_local: bool,
+    /// Keystore passphrase the keypairs are encrypted with
+    passphrase: String,
+    /// Profiles offered by the cluster selector
+    clusters: Clusters,
     output_logs: Vec<String>,
     is_running: bool,
 }
@@ -25,10 +50,17 @@ struct AppState {
 #[cfg(feature = "gui")]
 impl Default for AppState {
     fn default() -> Self {
+        // A broken user config leaves the built-in profiles
+        let clusters = config::clusters().unwrap_or_default();
+        let rpc_url = clusters
+            .get(DEFAULT_CLUSTER)
+            .map(|profile| profile.rpc_url.clone())
+            .unwrap_or_default();
         Self {
             project_name: "my-x402-agent".to_string(),
-            rpc_url: "https://api.devnet.solana.com".to_string(),
-            use_local: false,
+            rpc_url,
+            passphrase: String::new(),
+            clusters,
             output_logs: Vec::new(),
             is_running: false,
         }
@@ -37,6 +69,8 @@ impl Default for AppState {
 
 #[cfg(feature = "gui")]
 fn main() {
+    // A desktop window has no terminal to ask for the passphrase on
+    keystore::disable_prompt();
     dioxus::launch(App);
 }
 
@@ -70,6 +104,8 @@ fn App() -> Element {
 #[cfg(feature = "gui")]
 #[component]
 fn ConfigPanel(state: Signal<AppState>) -> Element {
+    let cluster_name = state().clusters.for_rpc_url(&state().rpc_url).name;
+
     rsx! {
         div {
             class: "panel config-panel",
@@ -101,34 +137,63 @@ fn ConfigPanel(state: Signal<AppState>) -> Element {
                     oninput: move |evt| {
                         state.write().rpc_url = evt.value().clone();
                     },
-                    disabled: state().is_running || state().use_local,
+                    disabled: state().is_running,
                 }
             }
 
             div {
-                class: "form-group checkbox-group",
+                class: "form-group",
+                label { r#for: "passphrase", "Keystore Passphrase:" }
                 input {
-                    id: "use-local",
-                    r#type: "checkbox",
-                    checked: state().use_local,
+                    id: "passphrase",
+                    class: "input",
+                    r#type: "password",
+                    value: "{state().passphrase}",
                     oninput: move |evt| {
-                        let checked = evt.value() == "true";
+                        state.write().passphrase = evt.value().clone();
+                    },
+                    disabled: state().is_running,
+                }
+            }
+
+            div {
+                class: "form-group",
+                label { r#for: "cluster", "Cluster:" }
+                select {
+                    id: "cluster",
+                    class: "input",
+                    value: "{cluster_name}",
+                    onchange: move |evt| {
                         let mut s = state.write();
-                        s.use_local = checked;
-                        if checked {
-                            s.rpc_url = "http://127.0.0.1:8899".to_string();
-                        } else {
-                            s.rpc_url = "https://api.devnet.solana.com".to_string();
+                        if let Ok(profile) = s.clusters.get(&evt.value()) {
+                            s.rpc_url = profile.rpc_url.clone();
                         }
                     },
                     disabled: state().is_running,
+                    for profile in state().clusters.profiles().to_vec() {
+                        option { value: "{profile.name}", "{profile.name}" }
+                    }
+                    option { value: "custom", disabled: true, "custom" }
                 }
-                label { r#for: "use-local", "Use Local Validator" }
             }
         }
     }
 }
 
+/// Append command progress to the output panel until the command finishes
+#[cfg(feature = "gui")]
+async fn show_events(
+    mut state: Signal<AppState>,
+    mut events: UnboundedReceiver<Event>,
+) {
+    while let Some(event) = events.recv().await {
+        // The outcome is summarized once the command returns
+        if !matches!(event, Event::Result(_)) {
+            state.write().output_logs.push(event.to_string());
+        }
+    }
+}
+
 #[cfg(feature = "gui")]
 #[component]
 fn CommandPanel(mut state: Signal<AppState>) -> Element {
@@ -140,14 +205,52 @@ fn CommandPanel(mut state: Signal<AppState>) -> Element {
             state.write().output_logs.push("Running init...".to_string());
 
             let project_name = state().project_name.clone();
-            let rpc_url = state().rpc_url.clone();
-
-            match init::run(&project_name, &rpc_url, false).await {
+            let cluster = state().clusters.for_rpc_url(&state().rpc_url);
+            // Keypairs are always encrypted, so a passphrase is required
+            let passphrase = state().passphrase.clone();
+            if passphrase.is_empty() {
+                state.write().output_logs.push(
+                    "✗ Init failed: Enter a keystore passphrase to encrypt \
+                    the keypairs"
+                        .to_string(),
+                );
+                state.write().is_running = false;
+                return;
+            }
+            keystore::set_passphrase(&passphrase);
+            let options = InitOptions {
+                passphrase: Some(passphrase),
+                ..Default::default()
+            };
+
+            let (reporter, events) = ChannelReporter::new();
+            // The reporter is dropped when the command finishes, which ends
+            // the event stream
+            let command = async move {
+                init::run(
+                    &Project::new(&project_name),
+                    &project_name,
+                    &cluster,
+                    &options,
+                    &reporter,
+                )
+                .await
+            };
+            let (result, _) =
+                futures_util::join!(command, show_events(state, events));
+
+            match result {
                 Ok(_) => {
-                    state.write().output_logs.push("✓ Init completed successfully".to_string());
+                    state
+                        .write()
+                        .output_logs
+                        .push("✓ Init completed successfully".to_string());
                 }
                 Err(e) => {
-                    state.write().output_logs.push(format!("✗ Init failed: {}", e));
+                    state
+                        .write()
+                        .output_logs
+                        .push(format!("✗ Init failed: {}", e));
                 }
             }
 
@@ -162,14 +265,46 @@ fn CommandPanel(mut state: Signal<AppState>) -> Element {
             state.write().output_logs.clear();
             state.write().output_logs.push("Running fund...".to_string());
 
-            let rpc_url = state().rpc_url.clone();
-
-            match fund::run(&rpc_url, false).await {
+            // Fund the project init created, not whichever one was loaded
+            // before
+            let project = Project::new(&state().project_name).overriding_env();
+            let cluster = state().clusters.for_rpc_url(&state().rpc_url);
+            keystore::set_passphrase(&state().passphrase);
+
+            let confirmer =
+                Confirmer::for_cluster(&cluster).unwrap_or_default();
+
+            let (reporter, events) = ChannelReporter::new();
+            // The GUI has no way to opt in, so mainnet is always refused
+            let command = async move {
+                let (_, confirmer) = MainnetGuard::default()
+                    .check(&cluster.client(), "fund", confirmer, &reporter)
+                    .await?;
+                fund::run(
+                    &project,
+                    &cluster,
+                    &confirmer,
+                    &RetryPolicy::default(),
+                    &fund::FundOptions::default(),
+                    &reporter,
+                )
+                .await
+            };
+            let (result, _) =
+                futures_util::join!(command, show_events(state, events));
+
+            match result {
                 Ok(_) => {
-                    state.write().output_logs.push("✓ Fund completed successfully".to_string());
+                    state
+                        .write()
+                        .output_logs
+                        .push("✓ Fund completed successfully".to_string());
                 }
                 Err(e) => {
-                    state.write().output_logs.push(format!("✗ Fund failed: {}", e));
+                    state
+                        .write()
+                        .output_logs
+                        .push(format!("✗ Fund failed: {}", e));
                 }
             }
 
@@ -184,14 +319,42 @@ fn CommandPanel(mut state: Signal<AppState>) -> Element {
             state.write().output_logs.clear();
             state.write().output_logs.push("Running test...".to_string());
 
-            let rpc_url = state().rpc_url.clone();
-
-            match test::run(&rpc_url, false).await {
+            let project = Project::new(&state().project_name).overriding_env();
+            let cluster = state().clusters.for_rpc_url(&state().rpc_url);
+            keystore::set_passphrase(&state().passphrase);
+
+            let confirmer =
+                Confirmer::for_cluster(&cluster).unwrap_or_default();
+
+            let (reporter, events) = ChannelReporter::new();
+            let command = async move {
+                let (_, confirmer) = MainnetGuard::default()
+                    .check(&cluster.client(), "test", confirmer, &reporter)
+                    .await?;
+                test::run(
+                    &project,
+                    &cluster,
+                    &confirmer,
+                    &RetryPolicy::default(),
+                    &reporter,
+                )
+                .await
+            };
+            let (result, _) =
+                futures_util::join!(command, show_events(state, events));
+
+            match result {
                 Ok(_) => {
-                    state.write().output_logs.push("✓ Test completed successfully".to_string());
+                    state
+                        .write()
+                        .output_logs
+                        .push("✓ Test completed successfully".to_string());
                 }
                 Err(e) => {
-                    state.write().output_logs.push(format!("✗ Test failed: {}", e));
+                    state
+                        .write()
+                        .output_logs
+                        .push(format!("✗ Test failed: {}", e));
                 }
             }
 
@@ -242,7 +405,10 @@ fn OutputPanel(state: Signal<AppState>) -> Element {
             div {
                 class: "output-box",
                 if state().output_logs.is_empty() {
-                    p { class: "placeholder", "Command output will appear here..." }
+                    p {
+                        class: "placeholder",
+                        "Command output will appear here..."
+                    }
                 } else {
                     for log in state().output_logs.iter() {
                         div {
@@ -1,9 +1,16 @@
+use crate::cluster::ClusterProfile;
+use crate::keystore;
+use crate::mnemonic::{
+    self, DerivationPath, FACILITATOR_ACCOUNT, PAYER_ACCOUNT,
+};
+use crate::project::Project;
+use crate::report::{self, Reporter};
+use crate::utils::generate_keypair;
+use crate::x402;
+use anyhow::Result;
+use serde::Serialize;
 use solana_sdk::signature::Signer;
 use std::fs;
-use std::path::Path;
-use anyhow::Result;
-use serde_json::json;
-use crate::utils::{log_action, log_info, generate_keypair, keypair_to_json, output_json};
 
 const CARGO_TOML_TEMPLATE: &str = r#"[package]
 name = "{{project_name}}"
@@ -27,15 +34,40 @@ async fn main() {
 
 const X402_TOML_TEMPLATE: &str = r#"# x402 Protocol Configuration
 [protocol]
-version = "0.1.0"
+version = {{x402_version}}
+scheme = "{{scheme}}"
+network = "{{network}}"
 
 [facilitator]
-# Facilitator program ID will be set after deployment
-program_id = "{{facilitator_program_id}}"
+# Facilitator used by `xforth serve` (see `xforth facilitator serve`)
+url = "http://127.0.0.1:3402"
 
 [payments]
 # Default payment settings for x402 transactions
 default_amount = 0.1
+# mint = "<mint address>"     # defaults to XUSD_MINT from .env
+# pay_to = "<wallet address>" # defaults to the Facilitator wallet
+
+[rpc]
+# XFORTH_CLUSTER and RPC_URL in .env, the environment or --cluster and
+# --rpc take precedence
+# cluster = "devnet"
+# url = "https://api.devnet.solana.com"
+# attempts = 5
+# retry_delay_ms = 500
+
+# Spend caps on mainnet-beta, in SOL
+[limits]
+# max_spend_per_run = 0.05
+# max_spend_per_day = 0.5
+
+# Paywalled routes served by `xforth serve`
+[[routes]]
+path = "/weather"
+price = 0.01
+description = "Current weather report"
+mime_type = "application/json"
+content = '{"weather":"sunny","temperature":21}'
 "#;
 
 const README_TEMPLATE: &str = r#"# {{project_name}}
@@ -66,6 +98,9 @@ const GITIGNORE_TEMPLATE: &str = r#"# Environment and secrets
 .env
 .env.*
 
+# Facilitator settlement ledger
+.xforth/
+
 # Rust
 target/
 Cargo.lock
@@ -79,41 +114,84 @@ Cargo.lock
 *~
 "#;
 
-pub async fn run(project_name: &str, rpc_url: &str, json_output: bool) -> Result<()> {
-    if !json_output {
-        log_info("Generating keypairs...");
-    }
+/// Keypairs generated by `xforth init`
+#[derive(Clone, Debug, Serialize)]
+pub struct InitReport {
+    pub project_name: String,
+    pub project_dir: String,
+    pub payer_pubkey: String,
+    pub facilitator_pubkey: String,
+    /// Mnemonic the keypairs were derived from, with `--mnemonic`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mnemonic: Option<String>,
+}
+
+/// Options for `xforth init`
+#[derive(Clone, Debug, Default)]
+pub struct InitOptions {
+    /// Passphrase the keypairs are encrypted with; stored as plaintext
+    /// otherwise
+    pub passphrase: Option<String>,
+    /// Derive the keypairs from a new BIP39 mnemonic instead of generating
+    /// them
+    pub mnemonic: bool,
+}
 
-    let payer_keypair = generate_keypair();
-    let facilitator_keypair = generate_keypair();
+/// Create the project `project_name` in the root of `project`
+pub async fn run(
+    project: &Project,
+    project_name: &str,
+    cluster: &ClusterProfile,
+    options: &InitOptions,
+    reporter: &dyn Reporter,
+) -> Result<InitReport> {
+    reporter.info("Generating keypairs...");
+
+    let phrase = options.mnemonic.then(mnemonic::generate);
+    let (payer_keypair, facilitator_keypair) = match &phrase {
+        Some(phrase) => (
+            mnemonic::derive(phrase, &DerivationPath::solana(PAYER_ACCOUNT))?,
+            mnemonic::derive(
+                phrase,
+                &DerivationPath::solana(FACILITATOR_ACCOUNT),
+            )?,
+        ),
+        None => (generate_keypair(), generate_keypair()),
+    };
 
     let payer_pubkey = payer_keypair.pubkey().to_string();
     let facilitator_pubkey = facilitator_keypair.pubkey().to_string();
 
-    if !json_output {
-        log_action(&format!("Generated Agent/Payer keypair: {}", payer_pubkey));
-        log_action(&format!("Generated Facilitator/Receiver keypair: {}", facilitator_pubkey));
-    }
+    reporter.step_finished(&format!(
+        "Generated Agent/Payer keypair: {}",
+        payer_pubkey
+    ));
+    reporter.step_finished(&format!(
+        "Generated Facilitator/Receiver keypair: {}",
+        facilitator_pubkey
+    ));
 
     // Create project directory
-    let project_dir = Path::new(project_name);
+    let project_dir = project.root();
     fs::create_dir_all(project_dir)?;
     fs::create_dir_all(project_dir.join("src"))?;
 
-    if !json_output {
-        log_info("Creating project template...");
-    }
+    reporter.info("Creating project template...");
 
     // Create Cargo.toml
-    let cargo_toml = CARGO_TOML_TEMPLATE.replace("{{project_name}}", project_name);
+    let cargo_toml =
+        CARGO_TOML_TEMPLATE.replace("{{project_name}}", project_name);
     fs::write(project_dir.join("Cargo.toml"), cargo_toml)?;
 
     // Create src/main.rs
     fs::write(project_dir.join("src/main.rs"), MAIN_RS_TEMPLATE)?;
 
     // Create x402.toml
-    let x402_toml = X402_TOML_TEMPLATE.replace("{{facilitator_program_id}}", &facilitator_pubkey);
-    fs::write(project_dir.join("x402.toml"), x402_toml)?;
+    let x402_toml = X402_TOML_TEMPLATE
+        .replace("{{x402_version}}", &x402::X402_VERSION.to_string())
+        .replace("{{scheme}}", x402::SCHEME_EXACT)
+        .replace("{{network}}", &cluster.network);
+    fs::write(project.manifest_path(), x402_toml)?;
 
     // Create README.md
     let readme = README_TEMPLATE.replace("{{project_name}}", project_name);
@@ -122,43 +200,55 @@ pub async fn run(project_name: &str, rpc_url: &str, json_output: bool) -> Result
     // Create .gitignore
     fs::write(project_dir.join(".gitignore"), GITIGNORE_TEMPLATE)?;
 
-    if !json_output {
-        log_action("Project template created");
-    }
-
-    // Placeholder deployment log
-    if !json_output {
-        log_info("Note: This is a placeholder deployment. In production, the actual x402 facilitator program would be deployed here.");
-        log_action(&format!("Facilitator deployed: {}", facilitator_pubkey));
-    }
+    reporter.step_finished("Project template created");
 
-    // Create .env file
-    let env_content = format!(
-        "PAYER_KEYPAIR={}\nFACILITATOR_KEYPAIR={}\nFACILITATOR_PROGRAM_ID={}\nRPC_URL={}\n",
-        keypair_to_json(&payer_keypair),
-        keypair_to_json(&facilitator_keypair),
-        facilitator_pubkey,
-        rpc_url
+    // Create or update .env file, keeping any existing comments and keys
+    let mut env_file = project.env_file()?;
+    let passphrase = options.passphrase.as_deref();
+    env_file
+        .set("PAYER_KEYPAIR", &keystore::store(&payer_keypair, passphrase)?);
+    env_file.set(
+        "FACILITATOR_KEYPAIR",
+        &keystore::store(&facilitator_keypair, passphrase)?,
     );
-    fs::write(project_dir.join(".env"), env_content)?;
-
-    if json_output {
-        output_json(&json!({
-            "command": "init",
-            "result": "success",
-            "project_name": project_name,
-            "payer_pubkey": payer_pubkey,
-            "facilitator_pubkey": facilitator_pubkey,
-            "facilitator_program_id": facilitator_pubkey,
-        }));
+    env_file.set("RPC_URL", &cluster.rpc_url);
+    env_file.save()?;
+
+    reporter.step_finished("Configuration file (.env) created");
+    if options.passphrase.is_some() {
+        reporter
+            .step_finished("Keypairs encrypted with the keystore passphrase");
     } else {
-        log_action("Configuration file (.env) created");
-        log_info("Project initialized successfully!");
-        println!("\nNext steps:");
-        println!("1. cd {}", project_name);
-        println!("2. xforth fund");
-        println!("3. xforth test");
+        reporter.warning(
+            "Secret keys are stored in plaintext in .env; run `xforth keys \
+            encrypt` to encrypt them",
+        );
     }
+    if let Some(phrase) = &phrase {
+        reporter.warning(
+            "Write down this mnemonic; it is the only way to \
+            recover the keypairs with `xforth keys recover`",
+        );
+        reporter.note(phrase);
+    }
+    reporter.info("Project initialized successfully!");
+    reporter.note(
+        "Payments are verified and settled by the Facilitator keypair; run \
+        `xforth facilitator serve` to start it",
+    );
+    reporter.note(&format!(
+        "\nNext steps:\n1. cd {}\n2. xforth fund\n3. xforth test",
+        project_dir.display()
+    ));
+
+    let report = InitReport {
+        project_name: project_name.to_string(),
+        project_dir: project_dir.display().to_string(),
+        payer_pubkey,
+        facilitator_pubkey,
+        mnemonic: phrase,
+    };
+    reporter.result(report::success("init", &report));
 
-    Ok(())
-}
\ No newline at end of file
+    Ok(report)
+}
@@ -0,0 +1,194 @@
+use crate::error::Error;
+use crate::keystore::{self, KEY_VARS};
+use crate::mnemonic::{
+    self, DerivationPath, FACILITATOR_ACCOUNT, PAYER_ACCOUNT,
+};
+use crate::project::Project;
+use crate::report::{self, Reporter};
+use crate::utils::{keypair_from_json, keypair_to_json};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use solana_sdk::signature::Signer;
+
+/// Outcome of `xforth keys encrypt` and `xforth keys decrypt`
+#[derive(Clone, Debug, Serialize)]
+pub struct KeysReport {
+    pub env_file: String,
+    /// Variables that were rewritten
+    pub changed: Vec<String>,
+    /// Variables that were already encrypted, or already plaintext
+    pub unchanged: Vec<String>,
+}
+
+/// Encrypt the project's plaintext keypairs in `.env` with `passphrase`
+pub fn encrypt(
+    project: &Project,
+    passphrase: &str,
+    reporter: &dyn Reporter,
+) -> Result<KeysReport> {
+    let mut env_file = project.env_file()?;
+    let mut report = KeysReport {
+        env_file: project.env_path().display().to_string(),
+        changed: Vec::new(),
+        unchanged: Vec::new(),
+    };
+
+    for var in KEY_VARS {
+        let value = env_file
+            .get(var)
+            .ok_or_else(|| Error::EnvVarMissing(var.to_string()))?;
+        if keystore::is_encrypted(value) {
+            reporter.info(&format!("{} is already encrypted", var));
+            report.unchanged.push(var.to_string());
+            continue;
+        }
+        let keypair = keypair_from_json(value)
+            .context(Error::KeypairInvalid(var.to_string()))?;
+        let encrypted = keystore::encrypt(&keypair, passphrase)?;
+        env_file.set(var, &encrypted);
+        reporter.step_finished(&format!(
+            "Encrypted {} ({})",
+            var,
+            keypair.pubkey()
+        ));
+        report.changed.push(var.to_string());
+    }
+
+    env_file.save()?;
+    reporter.note(&format!(
+        "Commands now ask for the passphrase, or read it from {}",
+        keystore::PASSPHRASE_VAR
+    ));
+    reporter.result(report::success("keys encrypt", &report));
+    Ok(report)
+}
+
+/// Decrypt the project's keypairs in `.env` back to plaintext JSON byte arrays
+pub fn decrypt(
+    project: &Project,
+    passphrase: &str,
+    reporter: &dyn Reporter,
+) -> Result<KeysReport> {
+    let mut env_file = project.env_file()?;
+    let mut report = KeysReport {
+        env_file: project.env_path().display().to_string(),
+        changed: Vec::new(),
+        unchanged: Vec::new(),
+    };
+
+    for var in KEY_VARS {
+        let value = env_file
+            .get(var)
+            .ok_or_else(|| Error::EnvVarMissing(var.to_string()))?;
+        if !keystore::is_encrypted(value) {
+            reporter.info(&format!("{} is not encrypted", var));
+            report.unchanged.push(var.to_string());
+            continue;
+        }
+        let keypair = keystore::decrypt(value, passphrase)
+            .context(format!("Failed to decrypt {}", var))?;
+        env_file.set(var, &keypair_to_json(&keypair));
+        reporter.step_finished(&format!(
+            "Decrypted {} ({})",
+            var,
+            keypair.pubkey()
+        ));
+        report.changed.push(var.to_string());
+    }
+
+    env_file.save()?;
+    if !report.changed.is_empty() {
+        reporter.warning(&format!(
+            "Secret keys are stored in plaintext in {}",
+            report.env_file
+        ));
+    }
+    reporter.result(report::success("keys decrypt", &report));
+    Ok(report)
+}
+
+/// Keypair derived by `xforth keys derive`
+#[derive(Clone, Debug, Serialize)]
+pub struct DeriveReport {
+    pub path: String,
+    pub pubkey: String,
+}
+
+/// Derive the public key at `path` from the mnemonic `phrase`
+pub fn derive(
+    phrase: &str,
+    path: &str,
+    reporter: &dyn Reporter,
+) -> Result<DeriveReport> {
+    let path: DerivationPath = path.parse()?;
+    let keypair = mnemonic::derive(phrase, &path)?;
+    let report = DeriveReport {
+        path: path.to_string(),
+        pubkey: keypair.pubkey().to_string(),
+    };
+    reporter.step_finished(&format!("{}: {}", report.path, report.pubkey));
+    reporter.result(report::success("keys derive", &report));
+    Ok(report)
+}
+
+/// Keypairs restored by `xforth keys recover`
+#[derive(Clone, Debug, Serialize)]
+pub struct RecoverReport {
+    pub env_file: String,
+    pub payer_pubkey: String,
+    pub facilitator_pubkey: String,
+    pub encrypted: bool,
+}
+
+/// Restore the Payer and Facilitator keypairs in the project's `.env` from
+/// the mnemonic `phrase`, encrypted with `passphrase` if given
+pub fn recover(
+    project: &Project,
+    phrase: &str,
+    passphrase: Option<&str>,
+    reporter: &dyn Reporter,
+) -> Result<RecoverReport> {
+    let payer_keypair =
+        mnemonic::derive(phrase, &DerivationPath::solana(PAYER_ACCOUNT))?;
+    let facilitator_keypair = mnemonic::derive(
+        phrase,
+        &DerivationPath::solana(FACILITATOR_ACCOUNT),
+    )?;
+    let report = RecoverReport {
+        env_file: project.env_path().display().to_string(),
+        payer_pubkey: payer_keypair.pubkey().to_string(),
+        facilitator_pubkey: facilitator_keypair.pubkey().to_string(),
+        encrypted: passphrase.is_some(),
+    };
+
+    let mut env_file = project.env_file()?;
+    for (var, keypair) in [
+        ("PAYER_KEYPAIR", &payer_keypair),
+        ("FACILITATOR_KEYPAIR", &facilitator_keypair),
+    ] {
+        if env_file.get(var).is_some_and(|value| {
+            keystore::pubkey_of(value).ok() != Some(keypair.pubkey())
+        }) {
+            reporter.warning(&format!(
+                "Replacing {}, which held a different keypair",
+                var
+            ));
+        }
+        env_file.set(var, &keystore::store(keypair, passphrase)?);
+        reporter.step_finished(&format!(
+            "Recovered {} ({})",
+            var,
+            keypair.pubkey()
+        ));
+    }
+    env_file.save()?;
+    if passphrase.is_none() {
+        reporter.warning(&format!(
+            "Secret keys are stored in plaintext in {}",
+            report.env_file
+        ));
+    }
+
+    reporter.result(report::success("keys recover", &report));
+    Ok(report)
+}
@@ -0,0 +1,301 @@
+use crate::error::Error;
+use crate::utils::{keypair_from_json, keypair_to_json};
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use std::env;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Prefix of encrypted keypairs in `.env`
+pub const ENCRYPTED_PREFIX: &str = "enc:";
+
+/// Format version written after [`ENCRYPTED_PREFIX`]
+pub const KEYSTORE_VERSION: &str = "v1";
+
+/// Variable the keystore passphrase is read from before prompting for it
+pub const PASSPHRASE_VAR: &str = "XFORTH_PASSPHRASE";
+
+/// Variables of `.env` that hold secret keys
+pub const KEY_VARS: [&str; 2] = ["PAYER_KEYPAIR", "FACILITATOR_KEYPAIR"];
+
+/// scrypt cost for new keystores: 2^15 iterations and 32 MiB of memory
+pub const SCRYPT_LOG_N: u8 = 15;
+
+/// Highest scrypt cost accepted when decrypting: 1 GiB of memory
+pub const MAX_SCRYPT_LOG_N: u8 = 20;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Passphrase entered at the prompt, so it is asked for once per process
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Whether the passphrase may be asked for on the terminal
+static PROMPT: AtomicBool = AtomicBool::new(true);
+
+/// Whether an `.env` value is an encrypted keypair
+pub fn is_encrypted(value: &str) -> bool {
+    value.trim().starts_with(ENCRYPTED_PREFIX)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], log_n: u8) -> Result<Key> {
+    let params =
+        scrypt::Params::new(log_n, SCRYPT_R, SCRYPT_P, 32).map_err(|err| {
+            Error::InvalidInput(format!(
+                "Invalid scrypt cost {}: {}",
+                log_n, err
+            ))
+        })?;
+    let mut key = Key::default();
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key).map_err(
+        |err| anyhow::anyhow!("Failed to derive the keystore key: {}", err),
+    )?;
+    Ok(key)
+}
+
+/// Encrypt `keypair` with a key derived from `passphrase` by scrypt, using
+/// ChaCha20-Poly1305. The result reads
+/// `enc:v1:<pubkey>:<log_n>:<salt>:<nonce>:<ciphertext>`, with base64 fields;
+/// the public key stays readable and is authenticated with the secret.
+pub fn encrypt(keypair: &Keypair, passphrase: &str) -> Result<String> {
+    let salt: [u8; SALT_LEN] = rand::random();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let pubkey = keypair.pubkey();
+
+    let key = derive_key(passphrase, &salt, SCRYPT_LOG_N)?;
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload { msg: &keypair.to_bytes(), aad: pubkey.as_ref() },
+        )
+        .map_err(|_| {
+            anyhow::anyhow!("Failed to encrypt keypair {}", pubkey)
+        })?;
+
+    Ok(format!(
+        "{}{}:{}:{}:{}:{}:{}",
+        ENCRYPTED_PREFIX,
+        KEYSTORE_VERSION,
+        pubkey,
+        SCRYPT_LOG_N,
+        BASE64.encode(salt),
+        BASE64.encode(nonce),
+        BASE64.encode(ciphertext)
+    ))
+}
+
+/// Value of `keypair` in `.env`: encrypted with `passphrase` if given,
+/// otherwise a JSON byte array
+pub fn store(keypair: &Keypair, passphrase: Option<&str>) -> Result<String> {
+    match passphrase {
+        Some(passphrase) => encrypt(keypair, passphrase),
+        None => Ok(keypair_to_json(keypair)),
+    }
+}
+
+/// Fields of an encrypted keypair
+struct Sealed {
+    pubkey: Pubkey,
+    log_n: u8,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+fn parse(value: &str) -> Result<Sealed> {
+    let invalid = || Error::KeypairInvalid("The encrypted value".to_string());
+    let fields =
+        value.trim().strip_prefix(ENCRYPTED_PREFIX).ok_or_else(invalid)?;
+    let [version, pubkey, log_n, salt, nonce, ciphertext] =
+        fields.split(':').collect::<Vec<_>>()[..]
+    else {
+        return Err(invalid().into());
+    };
+    if version != KEYSTORE_VERSION {
+        return Err(Error::InvalidInput(format!(
+            "Keystore version '{}' is not supported",
+            version
+        ))
+        .into());
+    }
+    let sealed = Sealed {
+        pubkey: Pubkey::from_str(pubkey).map_err(|_| invalid())?,
+        log_n: log_n.parse().map_err(|_| invalid())?,
+        salt: BASE64.decode(salt).map_err(|_| invalid())?,
+        nonce: BASE64.decode(nonce).map_err(|_| invalid())?,
+        ciphertext: BASE64.decode(ciphertext).map_err(|_| invalid())?,
+    };
+    if sealed.nonce.len() != NONCE_LEN {
+        return Err(invalid().into());
+    }
+    // The cost is read before anything is authenticated, so an edited value
+    // must not make decryption take unbounded time and memory
+    if !(SCRYPT_LOG_N..=MAX_SCRYPT_LOG_N).contains(&sealed.log_n) {
+        return Err(Error::InvalidInput(format!(
+            "Keystore scrypt cost {} is outside {}..={}",
+            sealed.log_n, SCRYPT_LOG_N, MAX_SCRYPT_LOG_N
+        ))
+        .into());
+    }
+    Ok(sealed)
+}
+
+/// Public key of an encrypted keypair, readable without the passphrase
+pub fn public_key(value: &str) -> Result<Pubkey> {
+    Ok(parse(value)?.pubkey)
+}
+
+/// Decrypt a keypair written by [`encrypt`]
+pub fn decrypt(value: &str, passphrase: &str) -> Result<Keypair> {
+    let sealed = parse(value)?;
+    let key = derive_key(passphrase, &sealed.salt, sealed.log_n)?;
+    let bytes = ChaCha20Poly1305::new(&key)
+        .decrypt(
+            Nonce::from_slice(&sealed.nonce),
+            Payload { msg: &sealed.ciphertext, aad: sealed.pubkey.as_ref() },
+        )
+        .map_err(|_| {
+            Error::PassphraseInvalid(format!(
+                "Wrong passphrase for keypair {}",
+                sealed.pubkey
+            ))
+        })?;
+
+    // As in `keypair_from_json`, the keypair is rebuilt from its secret half
+    let secret_key: [u8; 32] = bytes
+        .get(..32)
+        .and_then(|secret| secret.try_into().ok())
+        .ok_or_else(|| {
+            Error::KeypairInvalid(format!(
+                "The encrypted keypair {}",
+                sealed.pubkey
+            ))
+        })?;
+    let keypair = Keypair::new_from_array(secret_key);
+    if keypair.pubkey() != sealed.pubkey {
+        return Err(Error::KeypairInvalid(format!(
+            "The encrypted keypair {}",
+            sealed.pubkey
+        ))
+        .into());
+    }
+    Ok(keypair)
+}
+
+/// Use `passphrase` for the keystore instead of asking for it, for front ends
+/// without a terminal. An empty passphrase forgets the one set before.
+pub fn set_passphrase(passphrase: &str) {
+    *PASSPHRASE.lock().unwrap_or_else(PoisonError::into_inner) =
+        Some(passphrase.to_string()).filter(|p| !p.is_empty());
+}
+
+/// Never ask for the passphrase on the terminal: without `XFORTH_PASSPHRASE`
+/// or [`set_passphrase`], commands fail with `passphrase_missing`
+pub fn disable_prompt() {
+    PROMPT.store(false, Ordering::Relaxed);
+}
+
+/// Ask for a passphrase on the terminal, failing without one
+fn prompt(message: &str) -> Result<String> {
+    if !PROMPT.load(Ordering::Relaxed) {
+        return Err(Error::PassphraseMissing(format!(
+            "The keystore passphrase is needed. Enter it or set {}.",
+            PASSPHRASE_VAR
+        ))
+        .into());
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::PassphraseMissing(format!(
+            "The keystore passphrase is needed. Set {} or run in a terminal.",
+            PASSPHRASE_VAR
+        ))
+        .into());
+    }
+    rpassword::prompt_password(message)
+        .context("Failed to read the passphrase")
+}
+
+/// Passphrase of the project's keystore: `XFORTH_PASSPHRASE`, else the one
+/// set or asked for once on the terminal
+pub fn passphrase() -> Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    let mut cached = PASSPHRASE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(passphrase) = cached.as_ref() {
+        return Ok(passphrase.clone());
+    }
+    let passphrase = prompt("Keystore passphrase: ")?;
+    *cached = Some(passphrase.clone());
+    Ok(passphrase)
+}
+
+/// Passphrase for a new keystore: `XFORTH_PASSPHRASE`, else asked for twice
+/// on the terminal
+pub fn new_passphrase() -> Result<String> {
+    let from_env = env::var(PASSPHRASE_VAR).ok();
+    let passphrase = match &from_env {
+        Some(passphrase) => passphrase.clone(),
+        None => prompt("New keystore passphrase: ")?,
+    };
+    if passphrase.is_empty() {
+        return Err(Error::PassphraseInvalid(
+            "The passphrase must not be empty".to_string(),
+        )
+        .into());
+    }
+    if from_env.is_none() {
+        if prompt("Repeat the passphrase: ")? != passphrase {
+            return Err(Error::PassphraseInvalid(
+                "The passphrases do not match".to_string(),
+            )
+            .into());
+        }
+        *PASSPHRASE.lock().unwrap_or_else(PoisonError::into_inner) =
+            Some(passphrase.clone());
+    }
+    Ok(passphrase)
+}
+
+/// Load the keypair in the environment variable `env_var`, stored as a JSON
+/// byte array or encrypted. Encrypted keypairs need the [`passphrase`].
+pub fn load_keypair(env_var: &str) -> Result<Keypair> {
+    let value = env::var(env_var)
+        .map_err(|_| Error::EnvVarMissing(env_var.to_string()))?;
+    if is_encrypted(&value) {
+        decrypt(&value, &passphrase()?)
+            .context(format!("Failed to decrypt {}", env_var))
+    } else {
+        keypair_from_json(&value)
+            .context(Error::KeypairInvalid(env_var.to_string()))
+    }
+}
+
+/// Public key of an `.env` keypair value, encrypted or not, without the
+/// passphrase
+pub fn pubkey_of(value: &str) -> Result<Pubkey> {
+    if is_encrypted(value) {
+        public_key(value)
+    } else {
+        Ok(keypair_from_json(value)?.pubkey())
+    }
+}
+
+/// Public key of the keypair in `env_var`, without asking for the passphrase
+pub fn load_pubkey(env_var: &str) -> Result<Pubkey> {
+    let value = env::var(env_var)
+        .map_err(|_| Error::EnvVarMissing(env_var.to_string()))?;
+    pubkey_of(&value).context(Error::KeypairInvalid(env_var.to_string()))
+}
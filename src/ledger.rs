@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where the facilitator records settlements, relative to the project
+/// directory
+pub const DEFAULT_LEDGER_PATH: &str = ".xforth/ledger.jsonl";
+
+/// One settled payment
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Signature of the settled transaction
+    pub signature: String,
+    /// Nonce carried in the payment's memo instruction, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    pub payer: String,
+    pub pay_to: String,
+    pub asset: String,
+    /// Amount in the asset's base units
+    pub amount: u64,
+    pub decimals: u8,
+    pub resource: String,
+    pub network: String,
+    /// Unix timestamp of the settlement
+    pub settled_at: u64,
+}
+
+impl LedgerEntry {
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+/// Append-only JSON Lines file of settled payments, used to reject replays
+#[derive(Debug)]
+pub struct Ledger {
+    path: PathBuf,
+    entries: Vec<LedgerEntry>,
+    signatures: HashSet<String>,
+    nonces: HashSet<String>,
+    /// Signatures and nonces of settlements in flight, kept in memory only
+    reserved_signatures: HashSet<String>,
+    reserved_nonces: HashSet<String>,
+}
+
+impl Ledger {
+    /// Open a ledger, starting empty if the file does not exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                String::new()
+            }
+            Err(err) => {
+                return Err(err)
+                    .context(format!("Failed to read {}", path.display()))
+            }
+        };
+
+        let mut ledger = Self {
+            path,
+            entries: Vec::new(),
+            signatures: HashSet::new(),
+            nonces: HashSet::new(),
+            reserved_signatures: HashSet::new(),
+            reserved_nonces: HashSet::new(),
+        };
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: LedgerEntry =
+                serde_json::from_str(line).context(format!(
+                    "Invalid entry on line {} of {}",
+                    number + 1,
+                    ledger.path.display()
+                ))?;
+            ledger.index(entry);
+        }
+        Ok(ledger)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// All settlements, oldest first
+    pub fn entries(&self) -> &[LedgerEntry] {
+        &self.entries
+    }
+
+    /// Whether `signature` is settled or reserved
+    pub fn contains_signature(&self, signature: &str) -> bool {
+        self.signatures.contains(signature)
+            || self.reserved_signatures.contains(signature)
+    }
+
+    /// Whether `nonce` is settled or reserved
+    pub fn contains_nonce(&self, nonce: &str) -> bool {
+        self.nonces.contains(nonce) || self.reserved_nonces.contains(nonce)
+    }
+
+    /// Hold `signature` and `nonce` for a settlement in flight, so they are
+    /// rejected as replays until it is recorded or [`release`]d
+    ///
+    /// [`release`]: Ledger::release
+    pub fn reserve(&mut self, signature: &str, nonce: Option<&str>) {
+        self.reserved_signatures.insert(signature.to_string());
+        if let Some(nonce) = nonce {
+            self.reserved_nonces.insert(nonce.to_string());
+        }
+    }
+
+    /// Give up a reservation whose settlement failed
+    pub fn release(&mut self, signature: &str, nonce: Option<&str>) {
+        self.reserved_signatures.remove(signature);
+        if let Some(nonce) = nonce {
+            self.reserved_nonces.remove(nonce);
+        }
+    }
+
+    /// Append a settlement to the file and the in-memory index. If writing
+    /// fails, its reservation is kept so it still can't be replayed.
+    pub fn record(&mut self, entry: LedgerEntry) -> Result<()> {
+        if let Some(dir) =
+            self.path.parent().filter(|dir| !dir.as_os_str().is_empty())
+        {
+            fs::create_dir_all(dir)
+                .context(format!("Failed to create {}", dir.display()))?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(format!("Failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        file.sync_data()
+            .context(format!("Failed to write {}", self.path.display()))?;
+
+        self.release(&entry.signature, entry.nonce.as_deref());
+        self.index(entry);
+        Ok(())
+    }
+
+    fn index(&mut self, entry: LedgerEntry) {
+        self.signatures.insert(entry.signature.clone());
+        if let Some(nonce) = &entry.nonce {
+            self.nonces.insert(nonce.clone());
+        }
+        self.entries.push(entry);
+    }
+}
@@ -1,4 +1,25 @@
-pub mod init;
+pub mod cluster;
+pub mod config;
+pub mod confirm;
+pub mod error;
+pub mod facilitator;
 pub mod fund;
+pub mod guard;
+pub mod init;
+pub mod keys;
+pub mod keystore;
+pub mod ledger;
+pub mod manifest;
+pub mod mnemonic;
+#[cfg(feature = "offline")]
+pub mod offline;
+pub mod pay;
+pub mod project;
+pub mod report;
+pub mod retry;
+pub mod rpc;
+pub mod serve;
 pub mod test;
+pub mod token;
 pub mod utils;
+pub mod x402;
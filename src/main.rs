@@ -1,24 +1,90 @@
-use clap::{Parser, Subcommand};
 use anyhow::Result;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::sync::Arc;
 
-mod init;
-mod fund;
-mod test;
-mod utils;
+// Use the library modules instead of redeclaring them
+use xforth::config::{Config, ConfigFlags};
+use xforth::confirm::Confirmer;
+use xforth::error::Error;
+use xforth::guard::{MainnetGuard, Network, DEFAULT_SPEND_LOG_PATH};
+use xforth::project::Project;
+use xforth::report::{Event, OutputFormat, Reporter};
+use xforth::retry::RetryingRpc;
+use xforth::token::{MetadataFields, TokenProgram};
+use xforth::{
+    config, error, facilitator, fund, init, keys, keystore, ledger, mnemonic,
+    pay, serve, test,
+};
 
 #[derive(Parser)]
 #[command(name = "xforth")]
 #[command(version = "0.1.0")]
-#[command(about = "CLI tool for bootstrapping x402 Solana projects in under 90 seconds", long_about = None)]
+#[command(about = "CLI tool for bootstrapping x402 Solana projects in under \
+    90 seconds", long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
 
-    /// Override RPC endpoint
+    /// Cluster profile: devnet, testnet, mainnet, localnet or one from the
+    /// user config [default: devnet]
+    #[arg(long, global = true)]
+    cluster: Option<String>,
+
+    /// Override RPC endpoint (default: RPC_URL from the environment or .env,
+    /// else the cluster's)
     #[arg(long, global = true)]
     rpc: Option<String>,
 
-    /// Use local Solana validator
+    /// Override websocket endpoint used for confirmations (default: derived
+    /// from the RPC endpoint)
+    #[arg(long, global = true)]
+    ws: Option<String>,
+
+    /// Attempts per RPC call when it fails with a transient error [default: 5]
+    #[arg(
+        long,
+        global = true,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    rpc_attempts: Option<u32>,
+
+    /// Delay before the first RPC retry in milliseconds, doubled on every
+    /// further retry [default: 500]
+    #[arg(long, global = true)]
+    rpc_retry_delay: Option<u64>,
+
+    /// Fraction of each RPC retry delay that is randomized (0 to 1) [default:
+    /// 0.2]
+    #[arg(long, global = true)]
+    rpc_retry_jitter: Option<f64>,
+
+    /// Let commands that move value run on mainnet-beta
+    #[arg(long, global = true)]
+    allow_mainnet: bool,
+
+    /// Skip the confirmation asked for before running on mainnet-beta
+    #[arg(long, global = true)]
+    yes: bool,
+
+    /// SOL a command may spend on mainnet-beta [default: 0.05]
+    #[arg(long, global = true)]
+    max_spend_per_run: Option<f64>,
+
+    /// SOL the project may spend on mainnet-beta in 24 hours [default: 0.5]
+    #[arg(long, global = true)]
+    max_spend_per_day: Option<f64>,
+
+    /// Project directory (default: the nearest directory upwards holding a
+    /// .env; for init, the project name)
+    #[arg(long, global = true)]
+    project: Option<PathBuf>,
+
+    /// Environment file to use instead of the project's .env
+    #[arg(long, global = true)]
+    env_file: Option<PathBuf>,
+
+    /// Use local Solana validator (same as --cluster localnet)
     #[arg(long, global = true)]
     local: bool,
 
@@ -26,11 +92,45 @@ struct Cli {
     #[arg(long, global = true)]
     no_color: bool,
 
-    /// Output in JSON format
+    /// Output format: colored logs, a single JSON result, or one JSON event
+    /// per line
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Human
+    )]
+    output: OutputFormat,
+
+    /// Output in JSON format (same as --output json)
     #[arg(long, global = true)]
     json: bool,
 }
 
+impl Cli {
+    fn output_format(&self) -> OutputFormat {
+        if self.json {
+            OutputFormat::Json
+        } else {
+            self.output
+        }
+    }
+
+    fn config_flags(&self) -> ConfigFlags {
+        ConfigFlags {
+            cluster: self.cluster.clone(),
+            local: self.local,
+            rpc_url: self.rpc.clone(),
+            ws_url: self.ws.clone(),
+            rpc_attempts: self.rpc_attempts,
+            rpc_retry_delay_ms: self.rpc_retry_delay,
+            rpc_retry_jitter: self.rpc_retry_jitter,
+            max_spend_per_run: self.max_spend_per_run,
+            max_spend_per_day: self.max_spend_per_day,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize a new x402 project
@@ -38,39 +138,389 @@ enum Commands {
         /// Project name (default: "my-x402-agent")
         #[arg(default_value = "my-x402-agent")]
         name: String,
+
+        /// Store the keypairs unencrypted instead of with a passphrase
+        /// (prompted for, or XFORTH_PASSPHRASE)
+        #[arg(long)]
+        plaintext: bool,
+
+        /// Derive the keypairs from a new BIP39 mnemonic, printed once
+        #[arg(long)]
+        mnemonic: bool,
     },
     /// Fund wallets with SOL and mint test tokens
-    Fund,
+    Fund {
+        /// xUSD balance to top the Payer up to
+        #[arg(
+            long = "target-xusd",
+            alias = "amount",
+            default_value_t = fund::DEFAULT_XUSD_AMOUNT
+        )]
+        target_xusd: u64,
+
+        /// SOL balance to top each wallet up to
+        #[arg(long, default_value_t = fund::DEFAULT_TARGET_SOL)]
+        target_sol: f64,
+
+        /// SOL balance below which a wallet is topped up (airdrops are
+        /// skipped above it)
+        #[arg(long, default_value_t = fund::DEFAULT_MIN_SOL)]
+        min_sol: f64,
+
+        /// Token program for the xUSD mint
+        #[arg(long, value_enum, default_value_t = TokenProgram::SplToken)]
+        token_program: TokenProgram,
+
+        /// Transfer fee in basis points (token-2022 only)
+        #[arg(long, default_value_t = fund::DEFAULT_FEE_BASIS_POINTS)]
+        fee_bps: u16,
+
+        /// Maximum transfer fee in xUSD (token-2022 only)
+        #[arg(long, default_value_t = fund::DEFAULT_MAX_FEE)]
+        max_fee: u64,
+
+        /// Token name stored in the mint metadata (token-2022 only)
+        #[arg(long, default_value = "xForth Test USD")]
+        token_name: String,
+
+        /// Token symbol stored in the mint metadata (token-2022 only)
+        #[arg(long, default_value = "xUSD")]
+        token_symbol: String,
+
+        /// Token URI stored in the mint metadata (token-2022 only)
+        #[arg(long, default_value = "")]
+        token_uri: String,
+    },
     /// Validate payment flow with a test transaction
-    Test,
+    Test {
+        /// Run fund and test against an in-process Solana VM instead of the
+        /// cluster
+        #[arg(long)]
+        offline: bool,
+
+        /// Token program for the offline xUSD mint
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = TokenProgram::SplToken,
+            requires = "offline"
+        )]
+        token_program: TokenProgram,
+    },
+    /// Request a URL, paying for it over x402 if the server responds with 402
+    Pay {
+        /// URL of the paywalled resource
+        url: String,
+
+        /// Refuse to pay more than this many base units
+        #[arg(long)]
+        max_amount: Option<u64>,
+    },
+    /// Serve the paywalled routes configured in x402.toml
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = serve::DEFAULT_BIND)]
+        bind: String,
+
+        /// Facilitator base URL (default: facilitator.url from x402.toml)
+        #[arg(long)]
+        facilitator_url: Option<String>,
+    },
+    /// Run a local x402 facilitator
+    Facilitator {
+        #[command(subcommand)]
+        command: FacilitatorCommands,
+    },
+    /// Inspect the effective configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Manage the project's keypairs
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommands,
+    },
+}
+
+impl Commands {
+    /// Name reported as `command` in JSON output
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Init { .. } => "init",
+            Commands::Fund { .. } => "fund",
+            Commands::Test { .. } => "test",
+            Commands::Pay { .. } => "pay",
+            Commands::Serve { .. } => "serve",
+            Commands::Facilitator {
+                command: FacilitatorCommands::Serve { .. },
+            } => "facilitator serve",
+            Commands::Facilitator {
+                command: FacilitatorCommands::Ledger { .. },
+            } => "facilitator ledger",
+            Commands::Config { command: ConfigCommands::Show } => {
+                "config show"
+            }
+            Commands::Keys { command: KeysCommands::Encrypt } => {
+                "keys encrypt"
+            }
+            Commands::Keys { command: KeysCommands::Decrypt } => {
+                "keys decrypt"
+            }
+            Commands::Keys { command: KeysCommands::Derive { .. } } => {
+                "keys derive"
+            }
+            Commands::Keys { command: KeysCommands::Recover { .. } } => {
+                "keys recover"
+            }
+        }
+    }
+
+    /// Whether the command spends SOL or tokens, and so passes the mainnet
+    /// guard
+    fn moves_value(&self) -> bool {
+        matches!(
+            self,
+            Commands::Fund { .. }
+                | Commands::Test { offline: false, .. }
+                | Commands::Pay { .. }
+                | Commands::Facilitator {
+                    command: FacilitatorCommands::Serve { .. }
+                }
+        )
+    }
+}
+
+#[derive(Subcommand)]
+enum FacilitatorCommands {
+    /// Serve /verify, /settle and /supported using FACILITATOR_KEYPAIR
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = facilitator::DEFAULT_BIND)]
+        bind: String,
+
+        /// Settlement ledger used to reject replayed payments, relative to the
+        /// project
+        #[arg(long, default_value = ledger::DEFAULT_LEDGER_PATH)]
+        ledger: PathBuf,
+    },
+    /// List the payments settled by the facilitator
+    Ledger {
+        /// Settlement ledger to read, relative to the project
+        #[arg(long, default_value = ledger::DEFAULT_LEDGER_PATH)]
+        ledger: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print each setting, its value and where it came from
+    Show,
+}
+
+#[derive(Subcommand)]
+enum KeysCommands {
+    /// Encrypt the plaintext keypairs in .env with a passphrase
+    Encrypt,
+    /// Decrypt the keypairs in .env back to plaintext
+    Decrypt,
+    /// Print the public key at a derivation path of a mnemonic (prompted for,
+    /// or XFORTH_MNEMONIC)
+    Derive {
+        /// SLIP-10 derivation path; every index is hardened
+        #[arg(long, default_value = mnemonic::DEFAULT_PATH)]
+        path: String,
+    },
+    /// Restore the keypairs in .env from the mnemonic `init --mnemonic`
+    /// printed
+    Recover {
+        /// Store the recovered keypairs unencrypted instead of with a
+        /// passphrase
+        #[arg(long)]
+        plaintext: bool,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     let cli = Cli::parse();
+    let reporter = cli.output_format().reporter();
+    let command = cli.command.name();
+
+    if let Err(err) = run(cli, reporter.clone()).await {
+        let (code, exit_code) = error::classify(&err);
+        reporter.report(Event::Error {
+            command: command.to_string(),
+            code: code.to_string(),
+            message: format!("{:#}", err),
+        });
+        std::process::exit(exit_code);
+    }
+}
+
+async fn run(cli: Cli, shared_reporter: Arc<dyn Reporter>) -> Result<()> {
+    // Servers keep their own handle for their request handlers
+    let reporter = shared_reporter.as_ref();
 
     // Set global config
     if cli.no_color {
         colored::control::set_override(false);
     }
 
-    let rpc_url = if cli.local {
-        "http://127.0.0.1:8899".to_string()
+    let project = match &cli.command {
+        Commands::Init { name, .. } => {
+            let project = Project::new(
+                cli.project.clone().unwrap_or_else(|| PathBuf::from(name)),
+            );
+            match &cli.env_file {
+                Some(env_file) => project.with_env_file(env_file),
+                None => project,
+            }
+        }
+        _ => {
+            Project::discover(cli.project.as_deref(), cli.env_file.as_deref())?
+        }
+    };
+
+    // Flags, then the environment, the project and the user config
+    let config = Config::resolve(&cli.config_flags(), &project)?;
+    let cluster = &config.cluster.value;
+    let confirmer = Confirmer::for_cluster(cluster)?;
+    let retry = config.retry_policy();
+
+    // Identify the cluster by its genesis hash before anything is spent
+    let (network, confirmer) = if cli.command.moves_value() {
+        let guard = MainnetGuard {
+            allow_mainnet: cli.allow_mainnet,
+            assume_yes: cli.yes,
+            caps: config.spend_caps(),
+            spend_log: project.path(DEFAULT_SPEND_LOG_PATH),
+        };
+        let client = RetryingRpc::new(cluster.client(), retry);
+        guard.check(&client, cli.command.name(), confirmer, reporter).await?
     } else {
-        cli.rpc.unwrap_or_else(|| "https://api.devnet.solana.com".to_string())
+        (Network::Unknown, confirmer)
     };
 
     match cli.command {
-        Commands::Init { name } => {
-            init::run(&name, &rpc_url, cli.json).await?;
+        Commands::Init { name, plaintext, mnemonic } => {
+            let options = init::InitOptions {
+                passphrase: if plaintext {
+                    None
+                } else {
+                    Some(keystore::new_passphrase()?)
+                },
+                mnemonic,
+            };
+            init::run(&project, &name, cluster, &options, reporter).await?;
+        }
+        Commands::Fund {
+            target_xusd,
+            target_sol,
+            min_sol,
+            token_program,
+            fee_bps,
+            max_fee,
+            token_name,
+            token_symbol,
+            token_uri,
+        } => {
+            let options = fund::FundOptions {
+                xusd_amount: target_xusd,
+                target_sol,
+                min_sol,
+                token_program,
+                fee_basis_points: fee_bps,
+                max_fee,
+                metadata: MetadataFields {
+                    name: token_name,
+                    symbol: token_symbol,
+                    uri: token_uri,
+                },
+                airdrop: true,
+            };
+            fund::run(
+                &project, cluster, &confirmer, &retry, &options, reporter,
+            )
+            .await?;
+        }
+        Commands::Test { offline: false, .. } => {
+            test::run(&project, cluster, &confirmer, &retry, reporter).await?;
+        }
+        #[cfg(feature = "offline")]
+        Commands::Test { offline: true, token_program } => {
+            let options =
+                fund::FundOptions { token_program, ..Default::default() };
+            xforth::offline::run(&options, reporter).await?;
+        }
+        #[cfg(not(feature = "offline"))]
+        Commands::Test { offline: true, .. } => {
+            return Err(anyhow::anyhow!(
+                "xforth was built without the 'offline' feature"
+            ));
+        }
+        Commands::Pay { url, max_amount } => {
+            // The spend caps count SOL, so the token amount needs its own
+            // bound
+            if network == Network::Mainnet && max_amount.is_none() {
+                return Err(Error::InvalidInput(
+                    "On mainnet-beta, pay needs --max-amount".to_string(),
+                )
+                .into());
+            }
+            pay::run(&project, &url, cluster, &retry, max_amount, reporter)
+                .await?;
         }
-        Commands::Fund => {
-            fund::run(&rpc_url, cli.json).await?;
+        Commands::Serve { bind, facilitator_url } => {
+            serve::run(
+                &project,
+                &bind,
+                facilitator_url.as_deref(),
+                cluster,
+                &retry,
+                shared_reporter.clone(),
+            )
+            .await?;
         }
-        Commands::Test => {
-            test::run(&rpc_url, cli.json).await?;
+        Commands::Facilitator { command } => match command {
+            FacilitatorCommands::Serve { bind, ledger } => {
+                facilitator::run(
+                    &project,
+                    &bind,
+                    cluster,
+                    confirmer,
+                    retry,
+                    &ledger,
+                    shared_reporter.clone(),
+                )
+                .await?;
+            }
+            FacilitatorCommands::Ledger { ledger } => {
+                facilitator::ledger(&project, &ledger, reporter).await?;
+            }
+        },
+        Commands::Config { command: ConfigCommands::Show } => {
+            config::show(&config, reporter);
+        }
+        Commands::Keys { command: KeysCommands::Encrypt } => {
+            keys::encrypt(&project, &keystore::new_passphrase()?, reporter)?;
+        }
+        Commands::Keys { command: KeysCommands::Decrypt } => {
+            keys::decrypt(&project, &keystore::passphrase()?, reporter)?;
+        }
+        Commands::Keys { command: KeysCommands::Derive { path } } => {
+            keys::derive(&mnemonic::phrase()?, &path, reporter)?;
+        }
+        Commands::Keys { command: KeysCommands::Recover { plaintext } } => {
+            let phrase = mnemonic::phrase()?;
+            let passphrase = if plaintext {
+                None
+            } else {
+                Some(keystore::new_passphrase()?)
+            };
+            keys::recover(&project, &phrase, passphrase.as_deref(), reporter)?;
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
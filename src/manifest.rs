@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Project configuration stored in `x402.toml`
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub protocol: ProtocolConfig,
+    #[serde(default)]
+    pub facilitator: FacilitatorConfig,
+    #[serde(default)]
+    pub payments: PaymentsConfig,
+    #[serde(default)]
+    pub rpc: RpcConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ProtocolConfig {
+    pub version: Option<u8>,
+    pub scheme: Option<String>,
+    pub network: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct FacilitatorConfig {
+    /// Base URL of the facilitator used by `xforth serve`
+    pub url: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PaymentsConfig {
+    pub default_amount: Option<Price>,
+    /// Mint paid with when a route does not set one (defaults to `XUSD_MINT`)
+    pub mint: Option<String>,
+    /// Recipient when a route does not set one (defaults to the Facilitator)
+    pub pay_to: Option<String>,
+}
+
+/// RPC settings, also accepted in the user config file.
+/// Command-line flags and environment variables take precedence.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RpcConfig {
+    /// Name of a cluster profile, see `xforth config show`
+    pub cluster: Option<String>,
+    pub url: Option<String>,
+    /// Websocket endpoint for confirmations (defaults to one derived from
+    /// `url`)
+    pub ws_url: Option<String>,
+    pub attempts: Option<u32>,
+    pub retry_delay_ms: Option<u64>,
+    pub retry_jitter: Option<f64>,
+}
+
+/// Caps on the SOL spent on mainnet, also accepted in the user config file
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct LimitsConfig {
+    /// SOL a single command may spend
+    pub max_spend_per_run: Option<f64>,
+    /// SOL all commands of the project may spend in 24 hours
+    pub max_spend_per_day: Option<f64>,
+}
+
+/// A paywalled route served by `xforth serve`
+#[derive(Clone, Debug, Deserialize)]
+pub struct RouteConfig {
+    pub path: String,
+    /// Price in whole tokens (defaults to `payments.default_amount`)
+    pub price: Option<Price>,
+    pub mint: Option<String>,
+    pub pay_to: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_mime_type")]
+    pub mime_type: String,
+    /// Body returned once the payment is settled
+    #[serde(default)]
+    pub content: String,
+}
+
+/// Amount of tokens as written in `x402.toml`, either a number or a string
+/// such as `"0.000001"`, kept as decimal text so it converts to base units
+/// exactly
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Price(String);
+
+impl Price {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Integer(u64),
+            // Displayed as the shortest decimal that parses back to it, so
+            // `0.01` stays "0.01"
+            Float(f64),
+        }
+        Ok(Price(match Raw::deserialize(deserializer)? {
+            Raw::Text(text) => text,
+            Raw::Integer(integer) => integer.to_string(),
+            Raw::Float(float) => float.to_string(),
+        }))
+    }
+}
+
+fn default_mime_type() -> String {
+    "text/plain".to_string()
+}
+
+impl Manifest {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content)
+            .context(format!("Failed to parse {}", path.display()))
+    }
+}
@@ -0,0 +1,135 @@
+use crate::error::Error;
+use anyhow::{Context, Result};
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use solana_sdk::signature::Keypair;
+use std::env;
+use std::fmt;
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+/// Variable the mnemonic is read from before prompting for it
+pub const MNEMONIC_VAR: &str = "XFORTH_MNEMONIC";
+
+/// Account index of the Payer in `m/44'/501'/n'/0'`
+pub const PAYER_ACCOUNT: u32 = 0;
+
+/// Account index of the Facilitator in `m/44'/501'/n'/0'`
+pub const FACILITATOR_ACCOUNT: u32 = 1;
+
+/// Path of the first Solana account, as Phantom and Solflare derive it
+pub const DEFAULT_PATH: &str = "m/44'/501'/0'/0'";
+
+/// Entropy of generated mnemonics: 128 bits, or 12 words
+const ENTROPY_LEN: usize = 16;
+
+const HARDENED: u32 = 0x8000_0000;
+
+/// A SLIP-10 derivation path. ed25519 only has hardened keys, so every
+/// index is hardened whether or not it is written with `'`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// `m/44'/501'/account'/0'`, the path wallets use for Solana accounts
+    pub fn solana(account: u32) -> Self {
+        Self(vec![44, 501, account, 0])
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Error;
+
+    fn from_str(path: &str) -> Result<Self, Error> {
+        let invalid = || {
+            Error::InvalidInput(format!(
+                "Invalid derivation path '{}', expected e.g. {}",
+                path, DEFAULT_PATH
+            ))
+        };
+        let mut segments = path.trim().split('/');
+        if segments.next() != Some("m") {
+            return Err(invalid());
+        }
+        let indices = segments
+            .map(|segment| {
+                let index =
+                    segment.strip_suffix(['\'', 'h', 'H']).unwrap_or(segment);
+                index
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|index| *index < HARDENED)
+                    .ok_or_else(invalid)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self(indices))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            write!(f, "/{}'", index)?;
+        }
+        Ok(())
+    }
+}
+
+/// A new random 12-word BIP39 mnemonic
+pub fn generate() -> String {
+    let entropy: [u8; ENTROPY_LEN] = rand::random();
+    Mnemonic::from_entropy(&entropy)
+        .expect("16 bytes is a valid BIP39 entropy length")
+        .to_string()
+}
+
+/// BIP39 seed of `phrase`, without a BIP39 passphrase
+pub fn seed(phrase: &str) -> Result<[u8; 64]> {
+    let mnemonic =
+        Mnemonic::parse_normalized(phrase.trim()).map_err(|err| {
+            Error::InvalidInput(format!("Invalid mnemonic: {}", err))
+        })?;
+    Ok(mnemonic.to_seed(""))
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key)
+        .expect("HMAC takes keys of any length");
+    for part in data {
+        mac.update(part);
+    }
+    let output = mac.finalize().into_bytes();
+    let (key, chain_code) = output.split_at(32);
+    (key.try_into().unwrap(), chain_code.try_into().unwrap())
+}
+
+/// Derive the ed25519 keypair at `path` from a BIP39 seed, following SLIP-10
+pub fn derive_from_seed(seed: &[u8], path: &DerivationPath) -> Keypair {
+    let (mut key, mut chain_code) = hmac_sha512(b"ed25519 seed", &[seed]);
+    for index in &path.0 {
+        (key, chain_code) = hmac_sha512(
+            &chain_code,
+            &[&[0], &key, &(index | HARDENED).to_be_bytes()],
+        );
+    }
+    Keypair::new_from_array(key)
+}
+
+/// Derive the keypair at `path` from a BIP39 mnemonic
+pub fn derive(phrase: &str, path: &DerivationPath) -> Result<Keypair> {
+    Ok(derive_from_seed(&seed(phrase)?, path))
+}
+
+/// Mnemonic to recover from: `XFORTH_MNEMONIC`, else asked for on the terminal
+pub fn phrase() -> Result<String> {
+    if let Ok(phrase) = env::var(MNEMONIC_VAR) {
+        return Ok(phrase);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::EnvVarMissing(MNEMONIC_VAR.to_string()).into());
+    }
+    rpassword::prompt_password("Mnemonic: ")
+        .context("Failed to read the mnemonic")
+}
@@ -0,0 +1,316 @@
+use crate::confirm::Confirmer;
+use crate::fund::{self, FundOptions, XUSD_DECIMALS};
+use crate::report::{self, Reporter};
+use crate::rpc::RpcApi;
+use crate::test::{self, TEST_XUSD_AMOUNT};
+use crate::token::{self, MintInfo};
+use crate::utils::{env_file::EnvFile, truncate_pubkey};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use litesvm::LiteSVM;
+use serde::Serialize;
+use solana_rpc_client_api::client_error::{
+    ErrorKind as ClientErrorKind, Result as ClientResult,
+};
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    transaction::{
+        Result as TransactionResult, Transaction, TransactionError,
+    },
+};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Balances and signatures of an offline `fund` + `test` run
+#[derive(Clone, Debug, Serialize)]
+pub struct OfflineReport {
+    pub payer: String,
+    pub facilitator: String,
+    pub mint: String,
+    pub token_program: String,
+    pub mint_tx: String,
+    pub payment_tx: String,
+    /// Amounts below are in base units
+    pub amount: u64,
+    pub transfer_fee: u64,
+    pub payer_tokens_before: u64,
+    pub payer_tokens_after: u64,
+    pub facilitator_tokens_before: u64,
+    pub facilitator_tokens_after: u64,
+    pub payer_lamports_after: u64,
+    pub facilitator_lamports_after: u64,
+}
+
+/// In-process Solana VM preloaded with the SPL programs, usable wherever
+/// commands take an [`RpcApi`]
+pub struct SvmRpc {
+    svm: Mutex<LiteSVM>,
+}
+
+impl Default for SvmRpc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SvmRpc {
+    pub fn new() -> Self {
+        // The Token-2022 program bundled with LiteSVM can't reallocate
+        // accounts, as TokenMetadata needs, under SIMD-0219's stricter ABI
+        let mut feature_set = agave_feature_set::FeatureSet::all_enabled();
+        feature_set.deactivate(
+            &agave_feature_set::stricter_abi_and_runtime_constraints::id(),
+        );
+        let svm = LiteSVM::default()
+            .with_feature_set(feature_set)
+            .with_builtins()
+            .with_lamports(1_000_000 * LAMPORTS_PER_SOL)
+            .with_sysvars()
+            .with_default_programs()
+            .with_sigverify(true)
+            .with_blockhash_check(true);
+        Self { svm: Mutex::new(svm) }
+    }
+
+    fn svm(&self) -> MutexGuard<'_, LiteSVM> {
+        self.svm.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl RpcApi for SvmRpc {
+    async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64> {
+        Ok(self.svm().get_balance(pubkey).unwrap_or(0))
+    }
+
+    async fn request_airdrop(
+        &self,
+        pubkey: &Pubkey,
+        lamports: u64,
+    ) -> ClientResult<Signature> {
+        match self.svm().airdrop(pubkey, lamports) {
+            Ok(meta) => Ok(meta.signature),
+            Err(failed) => {
+                Err(ClientErrorKind::TransactionError(failed.err).into())
+            }
+        }
+    }
+
+    async fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        Ok(self.svm().latest_blockhash())
+    }
+
+    async fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        match self.svm().send_transaction(transaction.clone()) {
+            Ok(meta) => Ok(meta.signature),
+            Err(failed) => {
+                Err(ClientErrorKind::TransactionError(failed.err).into())
+            }
+        }
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        // Transactions execute as soon as they are sent
+        self.send_and_confirm_transaction(transaction).await
+    }
+
+    async fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+    ) -> ClientResult<bool> {
+        Ok(*blockhash == self.svm().latest_blockhash())
+    }
+
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> ClientResult<Option<TransactionResult<()>>> {
+        Ok(self.svm().get_transaction(signature).map(|result| match result {
+            Ok(_) => Ok(()),
+            Err(failed) => Err(failed.err.clone()),
+        }))
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> ClientResult<u64> {
+        Ok(self.svm().minimum_balance_for_rent_exemption(data_len))
+    }
+
+    async fn get_account(
+        &self,
+        pubkey: &Pubkey,
+    ) -> ClientResult<Option<Account>> {
+        Ok(self.svm().get_account(pubkey))
+    }
+
+    async fn get_genesis_hash(&self) -> ClientResult<Hash> {
+        // The VM has no genesis block, so it never passes for a public cluster
+        Ok(Hash::default())
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Option<TransactionError>> {
+        // Sigverify can only be switched off by rebuilding the VM
+        let mut svm = self.svm();
+        let unverified = std::mem::take(&mut *svm).with_sigverify(false);
+        let result = unverified.simulate_transaction(transaction.clone());
+        *svm = unverified.with_sigverify(true);
+        Ok(result.err().map(|failed| failed.err))
+    }
+}
+
+/// Run the `fund` and `test` flow against an in-process SVM, with no network.
+///
+/// Fresh Payer and Facilitator keypairs go through [`fund::run_with`] and
+/// [`test::run_with`] exactly as `xforth fund` and `xforth test` would, with
+/// an in-memory env file. Token balances are checked along the way and
+/// returned.
+pub async fn run_flow(
+    options: &FundOptions,
+    reporter: &dyn Reporter,
+) -> Result<OfflineReport> {
+    let cluster = SvmRpc::new();
+    let confirmer = Confirmer::default();
+    let payer = Keypair::new();
+    let facilitator = Keypair::new();
+
+    let funded = fund::run_with(
+        &cluster,
+        &confirmer,
+        &payer,
+        &facilitator,
+        &mut EnvFile::in_memory(),
+        options,
+        reporter,
+    )
+    .await?;
+
+    let mint_pubkey: Pubkey = funded.mint_pubkey.parse()?;
+    let mint_account = cluster
+        .get_account(&mint_pubkey)
+        .await?
+        .context("Mint account was not created")?;
+    let mint = MintInfo::from_account(&mint_pubkey, &mint_account)?;
+    let payer_token_account: Pubkey = funded.payer_token_account.parse()?;
+    let facilitator_token_account: Pubkey =
+        funded.facilitator_token_account.parse()?;
+
+    let xusd_amount = token::to_base_units(options.xusd_amount, mint.decimals)
+        .context("xUSD amount is too large")?;
+    let payer_tokens_before =
+        token::token_balance(&cluster, &payer_token_account).await?;
+    if payer_tokens_before != xusd_amount {
+        return Err(anyhow::anyhow!(
+            "Payer holds {} base units after funding, expected {}",
+            payer_tokens_before,
+            xusd_amount
+        ));
+    }
+    let facilitator_tokens_before =
+        token::token_balance(&cluster, &facilitator_token_account).await?;
+
+    let tested = test::run_with(
+        &cluster,
+        &confirmer,
+        &payer,
+        &facilitator,
+        &mint.address,
+        reporter,
+    )
+    .await?;
+
+    let amount = token::to_base_units(TEST_XUSD_AMOUNT, mint.decimals)
+        .context("Test amount is too large")?;
+    let payer_tokens_after =
+        token::token_balance(&cluster, &payer_token_account).await?;
+    let facilitator_tokens_after =
+        token::token_balance(&cluster, &facilitator_token_account).await?;
+    let transfer_fee =
+        mint.transfer_fee.map(|fee| fee.fee_for(amount)).unwrap_or(0);
+    if payer_tokens_after + amount != payer_tokens_before
+        || facilitator_tokens_before + amount - transfer_fee
+            != facilitator_tokens_after
+    {
+        return Err(anyhow::anyhow!(
+            "Unexpected token balances after test payment (payer: {} -> {}, \
+                facilitator: {} -> {})",
+            payer_tokens_before,
+            payer_tokens_after,
+            facilitator_tokens_before,
+            facilitator_tokens_after
+        ));
+    }
+
+    Ok(OfflineReport {
+        payer: payer.pubkey().to_string(),
+        facilitator: facilitator.pubkey().to_string(),
+        mint: mint.address.to_string(),
+        token_program: mint.program.name().to_string(),
+        mint_tx: funded.mint_tx.context("The xUSD mint was not created")?,
+        payment_tx: tested.transaction_signature,
+        amount,
+        transfer_fee,
+        payer_tokens_before,
+        payer_tokens_after,
+        facilitator_tokens_before,
+        facilitator_tokens_after,
+        payer_lamports_after: cluster.get_balance(&payer.pubkey()).await?,
+        facilitator_lamports_after: cluster
+            .get_balance(&facilitator.pubkey())
+            .await?,
+    })
+}
+
+/// `xforth test --offline`: run the flow in-process and report the balances
+pub async fn run(
+    options: &FundOptions,
+    reporter: &dyn Reporter,
+) -> Result<OfflineReport> {
+    reporter.step_started(&format!(
+        "Testing x402 payment flow offline ({})...",
+        options.token_program.name()
+    ));
+
+    let report = run_flow(options, reporter).await?;
+
+    let ui = |amount| token::to_ui_amount(amount, XUSD_DECIMALS);
+    reporter.info(&format!(
+        "Created xUSD mint {} and funded Payer {}",
+        truncate_pubkey(&report.mint),
+        truncate_pubkey(&report.payer)
+    ));
+    reporter.info(&format!("Payment successful! Tx: {}", report.payment_tx));
+    reporter.balance(
+        "Payer balance",
+        report.payer_lamports_after as f64 / LAMPORTS_PER_SOL as f64,
+        "SOL",
+    );
+    reporter.balance("Payer balance", ui(report.payer_tokens_after), "xUSD");
+    reporter.balance(
+        "Facilitator balance",
+        report.facilitator_lamports_after as f64 / LAMPORTS_PER_SOL as f64,
+        "SOL",
+    );
+    reporter.balance(
+        "Facilitator balance",
+        ui(report.facilitator_tokens_after),
+        "xUSD",
+    );
+    reporter.info("All offline tests passed!");
+    reporter.result(report::success("test --offline", &report));
+
+    Ok(report)
+}
@@ -0,0 +1,300 @@
+use crate::cluster::ClusterProfile;
+use crate::error::Error;
+use crate::keystore::load_keypair;
+use crate::project::Project;
+use crate::report::{self, Reporter};
+use crate::retry::{RetryPolicy, RetryingRpc};
+use crate::rpc::RpcApi;
+use crate::token::{self, MintInfo};
+use crate::utils::truncate_pubkey;
+use crate::x402::{
+    self, PaymentPayload, PaymentRequiredResponse, SettlementResponse,
+    PAYMENT_HEADER, PAYMENT_RESPONSE_HEADER,
+};
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use serde::Serialize;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+/// Outcome of `xforth pay`
+#[derive(Clone, Debug, Serialize)]
+pub struct PayReport {
+    pub url: String,
+    pub status: u16,
+    /// Whether the server asked for, and accepted, a payment
+    pub paid: bool,
+    /// Amount paid, in the asset's base units
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pay_to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    /// The payer's signature of the payment transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_signature: Option<String>,
+    /// Receipt from the `X-PAYMENT-RESPONSE` header, if the server sent one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settlement: Option<SettlementResponse>,
+    pub body: String,
+}
+
+/// Request `url`, paying for it with `PAYER_KEYPAIR` if the server answers 402
+pub async fn run(
+    project: &Project,
+    url: &str,
+    cluster: &ClusterProfile,
+    retry: &RetryPolicy,
+    max_amount: Option<u64>,
+    reporter: &dyn Reporter,
+) -> Result<PayReport> {
+    project.load_env()?;
+
+    let payer_keypair = load_keypair("PAYER_KEYPAIR")?;
+    let client = RetryingRpc::new(cluster.client(), *retry);
+    let report = run_with(
+        &client,
+        &payer_keypair,
+        url,
+        &cluster.network,
+        max_amount,
+        reporter,
+    )
+    .await?;
+    reporter.result(report::success("pay", &report));
+
+    Ok(report)
+}
+
+/// Request `url`, paying for it with `payer_keypair` through `client` if the
+/// server answers 402 for `network`. Unlike [`run`], it does not report the
+/// result.
+pub async fn run_with(
+    client: &impl RpcApi,
+    payer_keypair: &Keypair,
+    url: &str,
+    network: &str,
+    max_amount: Option<u64>,
+    reporter: &dyn Reporter,
+) -> Result<PayReport> {
+    let http = reqwest::Client::new();
+
+    reporter.step_started(&format!("Requesting {}...", url));
+
+    let response = http
+        .get(url)
+        .send()
+        .await
+        .context(format!("Failed to request {}", url))?;
+
+    if response.status() != StatusCode::PAYMENT_REQUIRED {
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "Request failed ({}): {}",
+                status,
+                body
+            ));
+        }
+        reporter.info(&format!("No payment required ({})", status));
+        reporter.note(&body);
+        return Ok(PayReport {
+            url: url.to_string(),
+            status: status.as_u16(),
+            paid: false,
+            amount: None,
+            asset: None,
+            pay_to: None,
+            network: None,
+            transaction_signature: None,
+            settlement: None,
+            body,
+        });
+    }
+
+    let required: PaymentRequiredResponse = response
+        .json()
+        .await
+        .context("Failed to parse 402 payment requirements")?;
+    let requirements = required.select().context(format!(
+        "Server does not offer a usable Solana 'exact' payment{}",
+        required
+            .error
+            .as_deref()
+            .map(|e| format!(" ({})", e))
+            .unwrap_or_default()
+    ))?;
+    if requirements.network != network {
+        return Err(Error::InvalidInput(format!(
+            "Server asks for a payment on {}, but the cluster is {}",
+            requirements.network, network
+        ))
+        .into());
+    }
+
+    let amount = requirements.amount()?;
+    if let Some(max) = max_amount {
+        if amount > max {
+            return Err(Error::PaymentFailed(format!(
+                "Payment of {} base units exceeds --max-amount {}",
+                amount, max
+            ))
+            .into());
+        }
+    }
+
+    let mint_address = requirements.asset()?;
+    let pay_to = requirements.pay_to()?;
+    let mint_account =
+        client.get_account(&mint_address).await?.ok_or_else(|| {
+            Error::NotFound(format!(
+                "Payment asset {} not found on this cluster",
+                mint_address
+            ))
+        })?;
+    let mint = MintInfo::from_account(&mint_address, &mint_account)?;
+
+    reporter.info(&format!(
+        "Payment required: {} to {} ({})",
+        token::to_ui_amount(amount, mint.decimals),
+        truncate_pubkey(&pay_to.to_string()),
+        requirements.description
+    ));
+
+    let fee_payer = requirements.fee_payer()?;
+    let transaction = build_payment(
+        client,
+        payer_keypair,
+        &mint,
+        &pay_to,
+        amount,
+        fee_payer.as_ref(),
+    )
+    .await?;
+    // The payer's signature identifies the payment, even when fees are
+    // sponsored
+    let signer_index = transaction
+        .message
+        .account_keys
+        .iter()
+        .position(|key| *key == payer_keypair.pubkey())
+        .context("Payer is not a signer of the payment transaction")?;
+    let signature = transaction.signatures[signer_index];
+
+    let header =
+        PaymentPayload::new(requirements, &transaction)?.to_header()?;
+
+    reporter.step_started(&format!(
+        "Retrying with signed payment. Tx: {}",
+        signature
+    ));
+
+    let response = http
+        .get(url)
+        .header(PAYMENT_HEADER, header)
+        .send()
+        .await
+        .context(format!("Failed to request {}", url))?;
+    let status = response.status();
+
+    let settlement = match response.headers().get(PAYMENT_RESPONSE_HEADER) {
+        Some(value) => Some(
+            SettlementResponse::from_header(value.to_str()?)
+                .context("Invalid X-PAYMENT-RESPONSE header")?,
+        ),
+        None => None,
+    };
+    let body = response.text().await?;
+
+    if !status.is_success() {
+        return Err(Error::PaymentFailed(format!(
+            "Payment was not accepted ({}): {}",
+            status, body
+        ))
+        .into());
+    }
+
+    reporter.step_finished(&format!("Payment accepted ({})", status));
+    if let Some(settlement) = &settlement {
+        reporter.info(&format!(
+            "Settlement: success={} network={} Tx: {}",
+            settlement.success, settlement.network, settlement.transaction
+        ));
+    }
+    reporter.note(&body);
+
+    Ok(PayReport {
+        url: url.to_string(),
+        status: status.as_u16(),
+        paid: true,
+        amount: Some(amount),
+        asset: Some(mint.address.to_string()),
+        pay_to: Some(pay_to.to_string()),
+        network: Some(requirements.network.clone()),
+        transaction_signature: Some(signature.to_string()),
+        settlement,
+        body,
+    })
+}
+
+/// Build and sign an SPL token transfer matching the payment requirements.
+///
+/// When the facilitator sponsors fees, it is set as fee payer, funds the
+/// recipient's token account if it must be created, and the transaction is
+/// only partially signed: the payer signs as token authority and the
+/// facilitator adds its signature at settlement.
+pub async fn build_payment(
+    client: &impl RpcApi,
+    payer: &Keypair,
+    mint: &MintInfo,
+    pay_to: &Pubkey,
+    amount: u64,
+    fee_payer: Option<&Pubkey>,
+) -> Result<Transaction> {
+    let source = token::associated_token_address(
+        &payer.pubkey(),
+        &mint.address,
+        mint.program,
+    );
+    let destination =
+        token::associated_token_address(pay_to, &mint.address, mint.program);
+
+    // A fresh nonce keeps the facilitator from treating two equal payments as
+    // a replay
+    let mut instructions = vec![x402::nonce_memo_ix(&x402::generate_nonce())];
+
+    // The recipient may not have a token account for this mint yet. Whoever
+    // pays the fee funds it, as a sponsoring facilitator only accepts that.
+    let fee_payer = fee_payer.copied().unwrap_or_else(|| payer.pubkey());
+    if client.get_account(&destination).await?.is_none() {
+        instructions.push(token::create_associated_token_account_ix(
+            &fee_payer,
+            pay_to,
+            &mint.address,
+            mint.program,
+        ));
+    }
+
+    instructions.push(token::transfer_checked_ix(
+        mint.program,
+        &source,
+        &mint.address,
+        &destination,
+        &payer.pubkey(),
+        amount,
+        mint.decimals,
+    )?);
+
+    let recent_blockhash = client.get_latest_blockhash().await?;
+    let mut transaction =
+        Transaction::new_with_payer(&instructions, Some(&fee_payer));
+    transaction.try_partial_sign(&[payer], recent_blockhash)?;
+    Ok(transaction)
+}
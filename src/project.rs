@@ -0,0 +1,120 @@
+use crate::error::Error;
+use crate::manifest::Manifest;
+use crate::utils::env_file::EnvFile;
+use anyhow::{Context, Result};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Name of the environment file in a project directory
+pub const ENV_FILE: &str = ".env";
+
+/// Name of the project configuration file in a project directory
+pub const MANIFEST_FILE: &str = "x402.toml";
+
+/// An xforth project: the directory `init` created, holding `.env` and
+/// `x402.toml`. Files commands read or write are resolved against its root
+/// rather than the current working directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Project {
+    root: PathBuf,
+    env_path: PathBuf,
+    override_env: bool,
+}
+
+impl Project {
+    /// The project in `root`, with its `.env` inside it
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let env_path = root.join(ENV_FILE);
+        Self { root, env_path, override_env: false }
+    }
+
+    /// Use `env_path` instead of the `.env` in the project root
+    pub fn with_env_file(mut self, env_path: impl Into<PathBuf>) -> Self {
+        self.env_path = env_path.into();
+        self
+    }
+
+    /// Let the project's `.env` replace variables already in the environment,
+    /// for processes that work on several projects, like `xforth-gui`
+    pub fn overriding_env(mut self) -> Self {
+        self.override_env = true;
+        self
+    }
+
+    /// The nearest directory from `start` upwards that holds a `.env`, as
+    /// dotenv searches
+    pub fn find(start: &Path) -> Option<Self> {
+        start
+            .ancestors()
+            .find(|dir| dir.join(ENV_FILE).is_file())
+            .map(Self::new)
+    }
+
+    /// Resolve the project from `--project` and `--env-file`.
+    ///
+    /// Without `--project`, the root is the directory of `--env-file` if
+    /// given, otherwise the nearest directory from the current one upwards
+    /// that holds a `.env`, falling back to the current directory.
+    pub fn discover(
+        project: Option<&Path>,
+        env_file: Option<&Path>,
+    ) -> Result<Self> {
+        let project = match (project, env_file) {
+            (Some(root), _) => Self::new(root),
+            (None, Some(env_path)) => match env_path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => Self::new(dir),
+                _ => Self::new("."),
+            },
+            (None, None) => {
+                let cwd = env::current_dir()
+                    .context("Failed to read the current directory")?;
+                Self::find(&cwd).unwrap_or_else(|| Self::new(cwd))
+            }
+        };
+        Ok(match env_file {
+            Some(env_path) => project.with_env_file(env_path),
+            None => project,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn env_path(&self) -> &Path {
+        &self.env_path
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.root.join(MANIFEST_FILE)
+    }
+
+    /// `path` relative to the project root; absolute paths are kept as they
+    /// are
+    pub fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Load the project's `.env` into the process environment. Variables
+    /// already set in the environment take precedence unless
+    /// [`Self::overriding_env`].
+    pub fn load_env(&self) -> Result<()> {
+        let loaded = if self.override_env {
+            dotenvy::from_path_override(&self.env_path)
+        } else {
+            dotenvy::from_path(&self.env_path)
+        };
+        loaded.context(Error::EnvFileMissing)?;
+        Ok(())
+    }
+
+    /// The project's `.env`, for editing
+    pub fn env_file(&self) -> Result<EnvFile> {
+        EnvFile::load(&self.env_path)
+    }
+
+    pub fn manifest(&self) -> Result<Manifest> {
+        Manifest::load(self.manifest_path())
+    }
+}
@@ -0,0 +1,238 @@
+use crate::utils::{
+    log_action, log_error, log_info, log_token_balance, log_warning,
+    output_json,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{json, Value};
+use solana_sdk::signature::Signature;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Progress and results reported by commands
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    StepStarted {
+        step: String,
+    },
+    StepFinished {
+        step: String,
+    },
+    Info {
+        message: String,
+    },
+    /// Free-form text, e.g. next steps after `init`
+    Note {
+        message: String,
+    },
+    Warning {
+        message: String,
+    },
+    TransactionSent {
+        label: String,
+        signature: String,
+    },
+    TransactionConfirmed {
+        label: String,
+        signature: String,
+    },
+    Balance {
+        label: String,
+        amount: f64,
+        symbol: String,
+    },
+    /// Final outcome of a command, the object printed by `--json`
+    Result(Value),
+    Error {
+        command: String,
+        code: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::StepStarted { step } | Event::StepFinished { step } => {
+                write!(f, "{}", step)
+            }
+            Event::Info { message } | Event::Note { message } => {
+                write!(f, "{}", message)
+            }
+            Event::Warning { message } => write!(f, "Warning: {}", message),
+            Event::TransactionSent { label, signature } => {
+                write!(f, "{} sent: {}", label, signature)
+            }
+            Event::TransactionConfirmed { label, signature } => {
+                write!(f, "{} confirmed: {}", label, signature)
+            }
+            Event::Balance { label, amount, symbol } => {
+                write!(f, "{}: {} {}", label, amount, symbol)
+            }
+            Event::Result(value) => write!(f, "{}", value),
+            Event::Error { message, .. } => write!(f, "Error: {}", message),
+        }
+    }
+}
+
+/// The `result` of a successful command: its name, `"result": "success"`
+/// and the fields of its report
+pub fn success(command: &str, report: &impl Serialize) -> Value {
+    let mut value = json!({
+        "command": command,
+        "result": "success",
+    });
+    if let (Some(object), Ok(Value::Object(fields))) =
+        (value.as_object_mut(), serde_json::to_value(report))
+    {
+        object.extend(fields);
+    }
+    value
+}
+
+/// Sink for [`Event`]s, so the CLI, CI and the GUI consume the same progress
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: Event);
+
+    fn step_started(&self, step: &str) {
+        self.report(Event::StepStarted { step: step.to_string() });
+    }
+
+    fn step_finished(&self, step: &str) {
+        self.report(Event::StepFinished { step: step.to_string() });
+    }
+
+    fn info(&self, message: &str) {
+        self.report(Event::Info { message: message.to_string() });
+    }
+
+    fn note(&self, message: &str) {
+        self.report(Event::Note { message: message.to_string() });
+    }
+
+    fn warning(&self, message: &str) {
+        self.report(Event::Warning { message: message.to_string() });
+    }
+
+    fn transaction_sent(&self, label: &str, signature: &Signature) {
+        self.report(Event::TransactionSent {
+            label: label.to_string(),
+            signature: signature.to_string(),
+        });
+    }
+
+    fn transaction_confirmed(&self, label: &str, signature: &Signature) {
+        self.report(Event::TransactionConfirmed {
+            label: label.to_string(),
+            signature: signature.to_string(),
+        });
+    }
+
+    fn balance(&self, label: &str, amount: f64, symbol: &str) {
+        self.report(Event::Balance {
+            label: label.to_string(),
+            amount,
+            symbol: symbol.to_string(),
+        });
+    }
+
+    fn result(&self, value: Value) {
+        self.report(Event::Result(value));
+    }
+}
+
+/// Colored log lines for people at a terminal. Transactions are already
+/// named by the steps that send them, and results by the final steps.
+pub struct HumanReporter;
+
+impl Reporter for HumanReporter {
+    fn report(&self, event: Event) {
+        match event {
+            Event::StepStarted { step } | Event::StepFinished { step } => {
+                log_action(&step)
+            }
+            Event::Info { message } => log_info(&message),
+            Event::Note { message } => println!("{}", message),
+            Event::Warning { message } => log_warning(&message),
+            Event::Balance { label, amount, symbol } => {
+                log_token_balance(&label, amount, &symbol)
+            }
+            Event::Error { message, .. } => log_error(&message),
+            Event::TransactionSent { .. }
+            | Event::TransactionConfirmed { .. }
+            | Event::Result(_) => {}
+        }
+    }
+}
+
+/// A single JSON object with the result or error, for scripts
+pub struct JsonReporter;
+
+impl Reporter for JsonReporter {
+    fn report(&self, event: Event) {
+        match event {
+            Event::Result(value) => output_json(&value),
+            Event::Error { command, code, message } => output_json(&json!({
+                "command": command,
+                "result": "error",
+                "code": code,
+                "message": message,
+            })),
+            _ => {}
+        }
+    }
+}
+
+/// Every event as one JSON line, for CI logs and other stream consumers
+pub struct NdjsonReporter;
+
+impl Reporter for NdjsonReporter {
+    fn report(&self, event: Event) {
+        if let Ok(line) = serde_json::to_string(&event) {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Forwards events to a channel, e.g. to show progress in `xforth-gui`
+pub struct ChannelReporter {
+    sender: UnboundedSender<Event>,
+}
+
+impl ChannelReporter {
+    pub fn new() -> (Self, UnboundedReceiver<Event>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self { sender }, receiver)
+    }
+}
+
+impl Reporter for ChannelReporter {
+    fn report(&self, event: Event) {
+        // Nobody listening is not an error
+        self.sender.send(event).ok();
+    }
+}
+
+/// `--output` formats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored log lines
+    #[default]
+    Human,
+    /// A single JSON object with the result
+    Json,
+    /// One JSON event per line
+    Ndjson,
+}
+
+impl OutputFormat {
+    pub fn reporter(&self) -> Arc<dyn Reporter> {
+        match self {
+            OutputFormat::Human => Arc::new(HumanReporter),
+            OutputFormat::Json => Arc::new(JsonReporter),
+            OutputFormat::Ndjson => Arc::new(NdjsonReporter),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::rpc::RpcApi;
+use async_trait::async_trait;
+use solana_rpc_client_api::{
+    client_error::{
+        Error as ClientError, ErrorKind as ClientErrorKind,
+        Result as ClientResult,
+    },
+    custom_error::{
+        JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+        JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+        JSON_RPC_SERVER_ERROR_SLOT_SKIPPED,
+    },
+    request::RpcError,
+};
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{
+        Result as TransactionResult, Transaction, TransactionError,
+    },
+};
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Default number of attempts per RPC call, including the first one
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Default delay before the first retry in milliseconds, doubled on every
+/// further retry
+pub const DEFAULT_BASE_DELAY_MS: u64 = 500;
+
+/// Default fraction of each delay that is randomized
+pub const DEFAULT_JITTER: f64 = 0.2;
+
+/// Upper bound on the delay between two attempts
+pub const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// How RPC calls are retried when they fail with a transient error
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts per call, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every further retry
+    pub base_delay: Duration,
+    /// Each delay is scaled by a random factor in `1 ± jitter`
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: Duration::from_millis(DEFAULT_BASE_DELAY_MS),
+            jitter: DEFAULT_JITTER,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self { max_attempts: 1, ..Default::default() }
+    }
+
+    /// Delay before retrying after the given failed attempt (1-based)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(MAX_DELAY);
+        if self.jitter > 0.0 {
+            let jitter = self.jitter.min(1.0);
+            delay.mul_f64(1.0 + rand::random_range(-jitter..=jitter))
+        } else {
+            delay
+        }
+    }
+
+    /// Run `call`, retrying it while it fails with a retryable error
+    pub async fn run<T, F, Fut>(&self, mut call: F) -> ClientResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Err(err)
+                    if attempt < self.max_attempts && is_retryable(&err) =>
+                {
+                    sleep(self.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether a failed RPC call may succeed if made again: transport errors,
+/// rate limits, server errors and nodes that are behind. Transaction and
+/// signing errors never are.
+pub fn is_retryable(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(reqwest_err) => {
+            is_rate_limited(err)
+                || reqwest_err.is_timeout()
+                || reqwest_err.is_connect()
+                || reqwest_err
+                    .status()
+                    .is_some_and(|status| status.is_server_error())
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code, ..
+        }) => {
+            matches!(
+                *code,
+                JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+                    | JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+                    | JSON_RPC_SERVER_ERROR_SLOT_SKIPPED
+            ) || err.get_transaction_error()
+                == Some(TransactionError::BlockhashNotFound)
+        }
+        _ => false,
+    }
+}
+
+/// Whether the RPC endpoint or faucet answered HTTP 429 Too Many Requests
+pub fn is_rate_limited(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Reqwest(err) => {
+            err.status().is_some_and(|status| status.as_u16() == 429)
+        }
+        _ => false,
+    }
+}
+
+/// Wraps another [`RpcApi`] and retries every call according to a
+/// [`RetryPolicy`]
+pub struct RetryingRpc<C> {
+    inner: C,
+    policy: RetryPolicy,
+}
+
+impl<C: RpcApi> RetryingRpc<C> {
+    pub fn new(inner: C, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+}
+
+#[async_trait]
+impl<C: RpcApi> RpcApi for RetryingRpc<C> {
+    async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64> {
+        self.policy.run(move || self.inner.get_balance(pubkey)).await
+    }
+
+    async fn request_airdrop(
+        &self,
+        pubkey: &Pubkey,
+        lamports: u64,
+    ) -> ClientResult<Signature> {
+        self.policy
+            .run(move || self.inner.request_airdrop(pubkey, lamports))
+            .await
+    }
+
+    async fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        self.policy.run(move || self.inner.get_latest_blockhash()).await
+    }
+
+    async fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        self.policy
+            .run(move || self.inner.send_and_confirm_transaction(transaction))
+            .await
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        // Resending is safe: the signature, and so the transaction, stays the
+        // same
+        self.policy.run(move || self.inner.send_transaction(transaction)).await
+    }
+
+    async fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+    ) -> ClientResult<bool> {
+        self.policy.run(move || self.inner.is_blockhash_valid(blockhash)).await
+    }
+
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> ClientResult<Option<TransactionResult<()>>> {
+        self.policy
+            .run(move || self.inner.get_signature_status(signature))
+            .await
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> ClientResult<u64> {
+        self.policy
+            .run(move || {
+                self.inner.get_minimum_balance_for_rent_exemption(data_len)
+            })
+            .await
+    }
+
+    async fn get_account(
+        &self,
+        pubkey: &Pubkey,
+    ) -> ClientResult<Option<Account>> {
+        self.policy.run(move || self.inner.get_account(pubkey)).await
+    }
+
+    async fn get_genesis_hash(&self) -> ClientResult<Hash> {
+        self.policy.run(move || self.inner.get_genesis_hash()).await
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Option<TransactionError>> {
+        self.policy
+            .run(move || self.inner.simulate_transaction(transaction))
+            .await
+    }
+}
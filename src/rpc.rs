@@ -0,0 +1,567 @@
+use async_trait::async_trait;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_rpc_client_api::client_error::{
+    Error as ClientError, ErrorKind as ClientErrorKind, Result as ClientResult,
+};
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{
+        Result as TransactionResult, Transaction, TransactionError,
+    },
+};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Lamports charged per signature by [`FakeRpc`]
+pub const FAKE_SIGNATURE_FEE: u64 = 5_000;
+
+/// The RPC calls made by `fund` and `test`, so commands can run against
+/// the cluster, an in-memory fake or an in-process VM
+#[async_trait]
+pub trait RpcApi: Send + Sync {
+    async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64>;
+    async fn request_airdrop(
+        &self,
+        pubkey: &Pubkey,
+        lamports: u64,
+    ) -> ClientResult<Signature>;
+    async fn get_latest_blockhash(&self) -> ClientResult<Hash>;
+    async fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature>;
+    /// Submit a transaction without waiting for it, see [`crate::confirm`]
+    async fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature>;
+    async fn is_blockhash_valid(&self, blockhash: &Hash)
+        -> ClientResult<bool>;
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> ClientResult<Option<TransactionResult<()>>>;
+    async fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> ClientResult<u64>;
+    /// Fetch an account, `None` if it does not exist
+    async fn get_account(
+        &self,
+        pubkey: &Pubkey,
+    ) -> ClientResult<Option<Account>>;
+    /// Hash of the cluster's genesis block, which identifies the cluster
+    async fn get_genesis_hash(&self) -> ClientResult<Hash>;
+    /// Run `transaction` without checking its signatures or landing it,
+    /// returning the error it would fail with
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Option<TransactionError>>;
+}
+
+#[async_trait]
+impl RpcApi for RpcClient {
+    async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64> {
+        RpcClient::get_balance(self, pubkey).await
+    }
+
+    async fn request_airdrop(
+        &self,
+        pubkey: &Pubkey,
+        lamports: u64,
+    ) -> ClientResult<Signature> {
+        RpcClient::request_airdrop(self, pubkey, lamports).await
+    }
+
+    async fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        RpcClient::get_latest_blockhash(self).await
+    }
+
+    async fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        RpcClient::send_and_confirm_transaction(self, transaction).await
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        RpcClient::send_transaction(self, transaction).await
+    }
+
+    async fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+    ) -> ClientResult<bool> {
+        RpcClient::is_blockhash_valid(self, blockhash, self.commitment()).await
+    }
+
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> ClientResult<Option<TransactionResult<()>>> {
+        RpcClient::get_signature_status(self, signature).await
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> ClientResult<u64> {
+        RpcClient::get_minimum_balance_for_rent_exemption(self, data_len).await
+    }
+
+    async fn get_account(
+        &self,
+        pubkey: &Pubkey,
+    ) -> ClientResult<Option<Account>> {
+        Ok(self
+            .get_account_with_commitment(pubkey, self.commitment())
+            .await?
+            .value)
+    }
+
+    async fn get_genesis_hash(&self) -> ClientResult<Hash> {
+        RpcClient::get_genesis_hash(self).await
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Option<TransactionError>> {
+        let config = RpcSimulateTransactionConfig {
+            sig_verify: false,
+            commitment: Some(self.commitment()),
+            ..Default::default()
+        };
+        Ok(self
+            .simulate_transaction_with_config(transaction, config)
+            .await?
+            .value
+            .err
+            .map(Into::into))
+    }
+}
+
+/// Build a client error with a custom message, e.g. to script failures
+pub fn rpc_error(message: impl Into<String>) -> ClientError {
+    ClientErrorKind::Custom(message.into()).into()
+}
+
+/// RPC method names, as recorded and scripted by [`RecordingRpc`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RpcMethod {
+    GetBalance,
+    RequestAirdrop,
+    GetLatestBlockhash,
+    SendAndConfirmTransaction,
+    SendTransaction,
+    IsBlockhashValid,
+    GetSignatureStatus,
+    GetMinimumBalanceForRentExemption,
+    GetAccount,
+    GetGenesisHash,
+    SimulateTransaction,
+}
+
+#[derive(Default)]
+struct FakeState {
+    accounts: HashMap<Pubkey, Account>,
+    statuses: HashMap<Signature, TransactionResult<()>>,
+    pending_polls: usize,
+    dropped_transactions: usize,
+    airdrop_status: Option<TransactionResult<()>>,
+    next_signature: u64,
+    blockhash: Hash,
+    genesis_hash: Hash,
+    sent: Vec<Transaction>,
+}
+
+/// In-memory cluster for deterministic tests.
+///
+/// Airdrops credit lamports directly and sent transactions are recorded and
+/// charged [`FAKE_SIGNATURE_FEE`] per signature, but their instructions are
+/// not executed. There is a single valid blockhash at a time.
+pub struct FakeRpc {
+    state: Mutex<FakeState>,
+}
+
+impl Default for FakeRpc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeRpc {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(FakeState {
+                blockhash: Hash::new_from_array([1; 32]),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Set the lamports of a system account
+    pub fn set_balance(&self, pubkey: &Pubkey, lamports: u64) {
+        self.state().accounts.entry(*pubkey).or_default().lamports = lamports;
+    }
+
+    pub fn set_account(&self, pubkey: &Pubkey, account: Account) {
+        self.state().accounts.insert(*pubkey, account);
+    }
+
+    /// Report new signatures as unconfirmed for this many status polls
+    pub fn set_pending_polls(&self, polls: usize) {
+        self.state().pending_polls = polls;
+    }
+
+    /// Accept the next `count` transactions but never land them, expiring
+    /// the blockhash each was signed with
+    pub fn drop_next_transactions(&self, count: usize) {
+        self.state().dropped_transactions = count;
+    }
+
+    /// Confirm later airdrops with this outcome instead of success
+    pub fn set_airdrop_status(&self, status: TransactionResult<()>) {
+        self.state().airdrop_status = Some(status);
+    }
+
+    /// Report this genesis hash, e.g. to pose as mainnet (default: all zeros)
+    pub fn set_genesis_hash(&self, genesis_hash: Hash) {
+        self.state().genesis_hash = genesis_hash;
+    }
+
+    /// Transactions sent so far
+    pub fn sent_transactions(&self) -> Vec<Transaction> {
+        self.state().sent.clone()
+    }
+
+    /// Deterministic signature for airdrops, which have no transaction here
+    fn next_signature(state: &mut FakeState) -> Signature {
+        state.next_signature += 1;
+        let mut bytes = [0; 64];
+        bytes[..8].copy_from_slice(&state.next_signature.to_le_bytes());
+        Signature::from(bytes)
+    }
+}
+
+#[async_trait]
+impl RpcApi for FakeRpc {
+    async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64> {
+        Ok(self
+            .state()
+            .accounts
+            .get(pubkey)
+            .map(|account| account.lamports)
+            .unwrap_or(0))
+    }
+
+    async fn request_airdrop(
+        &self,
+        pubkey: &Pubkey,
+        lamports: u64,
+    ) -> ClientResult<Signature> {
+        let mut state = self.state();
+        let status = state.airdrop_status.clone().unwrap_or(Ok(()));
+        if status.is_ok() {
+            state.accounts.entry(*pubkey).or_default().lamports += lamports;
+        }
+        let signature = Self::next_signature(&mut state);
+        state.statuses.insert(signature, status);
+        Ok(signature)
+    }
+
+    async fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        Ok(self.state().blockhash)
+    }
+
+    async fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        self.send_transaction(transaction).await
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        let mut state = self.state();
+        if transaction.message.recent_blockhash != state.blockhash {
+            return Err(ClientErrorKind::TransactionError(
+                TransactionError::BlockhashNotFound,
+            )
+            .into());
+        }
+
+        let fee = FAKE_SIGNATURE_FEE * transaction.signatures.len() as u64;
+        let fee_payer = transaction
+            .message
+            .account_keys
+            .first()
+            .copied()
+            .unwrap_or_default();
+        let account = state.accounts.entry(fee_payer).or_default();
+        if account.lamports < fee {
+            return Err(ClientErrorKind::TransactionError(
+                TransactionError::InsufficientFundsForFee,
+            )
+            .into());
+        }
+        account.lamports -= fee;
+
+        let signature =
+            transaction.signatures.first().copied().unwrap_or_default();
+        if state.dropped_transactions > 0 {
+            state.dropped_transactions -= 1;
+            state.blockhash =
+                Hash::new_from_array([state.sent.len() as u8 + 2; 32]);
+        } else {
+            state.statuses.insert(signature, Ok(()));
+        }
+        state.sent.push(transaction.clone());
+        Ok(signature)
+    }
+
+    async fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+    ) -> ClientResult<bool> {
+        Ok(*blockhash == self.state().blockhash)
+    }
+
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> ClientResult<Option<TransactionResult<()>>> {
+        let mut state = self.state();
+        if state.pending_polls > 0 {
+            state.pending_polls -= 1;
+            return Ok(None);
+        }
+        Ok(state.statuses.get(signature).cloned())
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> ClientResult<u64> {
+        // Same formula as the default rent: 3480 lamports per byte-year, 2
+        // years, 128 bytes overhead
+        Ok((data_len as u64 + 128) * 3_480 * 2)
+    }
+
+    async fn get_account(
+        &self,
+        pubkey: &Pubkey,
+    ) -> ClientResult<Option<Account>> {
+        Ok(self.state().accounts.get(pubkey).cloned())
+    }
+
+    async fn get_genesis_hash(&self) -> ClientResult<Hash> {
+        Ok(self.state().genesis_hash)
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Option<TransactionError>> {
+        // Instructions aren't executed, so only the blockhash can fail
+        Ok((transaction.message.recent_blockhash != self.state().blockhash)
+            .then_some(TransactionError::BlockhashNotFound))
+    }
+}
+
+/// One call seen by [`RecordingRpc`], with whether it succeeded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RpcCall {
+    pub method: RpcMethod,
+    pub ok: bool,
+}
+
+/// Wraps another [`RpcApi`] and records every call made through it.
+///
+/// Failures can be scripted per method with [`RecordingRpc::fail_next`];
+/// a failed call never reaches the inner client.
+pub struct RecordingRpc<C> {
+    inner: C,
+    calls: Mutex<Vec<RpcCall>>,
+    failures: Mutex<HashMap<RpcMethod, VecDeque<ClientError>>>,
+}
+
+impl<C: RpcApi> RecordingRpc<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            calls: Mutex::new(Vec::new()),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Make the next call to `method` fail with `error`. Failures queue up.
+    pub fn fail_next(&self, method: RpcMethod, error: ClientError) {
+        self.failures
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(method)
+            .or_default()
+            .push_back(error);
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Calls made so far, oldest first
+    pub fn calls(&self) -> Vec<RpcCall> {
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Number of calls made to `method`
+    pub fn count(&self, method: RpcMethod) -> usize {
+        self.calls().iter().filter(|call| call.method == method).count()
+    }
+
+    /// Run `call` unless a failure is scripted for `method`, and record the
+    /// outcome
+    async fn record<T>(
+        &self,
+        method: RpcMethod,
+        call: impl Future<Output = ClientResult<T>>,
+    ) -> ClientResult<T> {
+        let failure = self
+            .failures
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(&method)
+            .and_then(VecDeque::pop_front);
+        let result = match failure {
+            Some(error) => Err(error),
+            None => call.await,
+        };
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(RpcCall { method, ok: result.is_ok() });
+        result
+    }
+}
+
+#[async_trait]
+impl<C: RpcApi> RpcApi for RecordingRpc<C> {
+    async fn get_balance(&self, pubkey: &Pubkey) -> ClientResult<u64> {
+        self.record(RpcMethod::GetBalance, self.inner.get_balance(pubkey))
+            .await
+    }
+
+    async fn request_airdrop(
+        &self,
+        pubkey: &Pubkey,
+        lamports: u64,
+    ) -> ClientResult<Signature> {
+        self.record(
+            RpcMethod::RequestAirdrop,
+            self.inner.request_airdrop(pubkey, lamports),
+        )
+        .await
+    }
+
+    async fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        self.record(
+            RpcMethod::GetLatestBlockhash,
+            self.inner.get_latest_blockhash(),
+        )
+        .await
+    }
+
+    async fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        self.record(
+            RpcMethod::SendAndConfirmTransaction,
+            self.inner.send_and_confirm_transaction(transaction),
+        )
+        .await
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        self.record(
+            RpcMethod::SendTransaction,
+            self.inner.send_transaction(transaction),
+        )
+        .await
+    }
+
+    async fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+    ) -> ClientResult<bool> {
+        self.record(
+            RpcMethod::IsBlockhashValid,
+            self.inner.is_blockhash_valid(blockhash),
+        )
+        .await
+    }
+
+    async fn get_signature_status(
+        &self,
+        signature: &Signature,
+    ) -> ClientResult<Option<TransactionResult<()>>> {
+        self.record(
+            RpcMethod::GetSignatureStatus,
+            self.inner.get_signature_status(signature),
+        )
+        .await
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> ClientResult<u64> {
+        self.record(
+            RpcMethod::GetMinimumBalanceForRentExemption,
+            self.inner.get_minimum_balance_for_rent_exemption(data_len),
+        )
+        .await
+    }
+
+    async fn get_account(
+        &self,
+        pubkey: &Pubkey,
+    ) -> ClientResult<Option<Account>> {
+        self.record(RpcMethod::GetAccount, self.inner.get_account(pubkey))
+            .await
+    }
+
+    async fn get_genesis_hash(&self) -> ClientResult<Hash> {
+        self.record(RpcMethod::GetGenesisHash, self.inner.get_genesis_hash())
+            .await
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Option<TransactionError>> {
+        self.record(
+            RpcMethod::SimulateTransaction,
+            self.inner.simulate_transaction(transaction),
+        )
+        .await
+    }
+}
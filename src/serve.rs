@@ -0,0 +1,364 @@
+use crate::cluster::ClusterProfile;
+use crate::error::Error;
+use crate::keystore::load_pubkey;
+use crate::manifest::{Manifest, RouteConfig};
+use crate::project::Project;
+use crate::report::Reporter;
+use crate::retry::{RetryPolicy, RetryingRpc};
+use crate::rpc::RpcApi;
+use crate::token::{self, MintInfo};
+use crate::utils::truncate_pubkey;
+use crate::x402::{
+    self, FacilitatorRequest, PaymentPayload, PaymentRequiredResponse,
+    PaymentRequirements, SettlementResponse, SupportedResponse,
+    VerifyResponse, PAYMENT_HEADER, PAYMENT_RESPONSE_HEADER, SCHEME_EXACT,
+};
+use anyhow::{Context, Result};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Default address the resource server listens on
+pub const DEFAULT_BIND: &str = "127.0.0.1:4021";
+
+/// Facilitator used when neither `--facilitator-url` nor `x402.toml` set one
+pub const DEFAULT_FACILITATOR_URL: &str = "http://127.0.0.1:3402";
+
+/// How long a client has to settle a payment
+const MAX_TIMEOUT_SECONDS: u64 = 60;
+
+struct Route {
+    config: RouteConfig,
+    requirements: PaymentRequirements,
+}
+
+struct ResourceServer {
+    routes: HashMap<String, Route>,
+    facilitator_url: String,
+    http: reqwest::Client,
+    reporter: Arc<dyn Reporter>,
+}
+
+/// Serve the routes of `x402.toml` behind x402 payments
+pub async fn run(
+    project: &Project,
+    bind: &str,
+    facilitator_url: Option<&str>,
+    cluster: &ClusterProfile,
+    retry: &RetryPolicy,
+    reporter: Arc<dyn Reporter>,
+) -> Result<()> {
+    project.load_env()?;
+
+    let manifest = project.manifest()?;
+    if manifest.routes.is_empty() {
+        return Err(Error::InvalidInput(
+            "No [[routes]] configured in x402.toml".to_string(),
+        )
+        .into());
+    }
+
+    let facilitator_url = facilitator_url
+        .map(str::to_string)
+        .or_else(|| manifest.facilitator.url.clone())
+        .unwrap_or_else(|| DEFAULT_FACILITATOR_URL.to_string());
+    let network = manifest
+        .protocol
+        .network
+        .clone()
+        .unwrap_or_else(|| cluster.network.clone());
+
+    let client = RetryingRpc::new(cluster.client(), *retry);
+    let http = reqwest::Client::new();
+
+    // Clients build transactions with the facilitator as fee payer
+    let fee_payer = fetch_fee_payer(&http, &facilitator_url, &network).await?;
+    reporter.info(&format!(
+        "Facilitator {} sponsors transaction fees",
+        truncate_pubkey(&fee_payer.to_string())
+    ));
+
+    let mut routes = HashMap::new();
+    for config in &manifest.routes {
+        let requirements = route_requirements(
+            &client, &manifest, config, bind, &network, &fee_payer,
+        )
+        .await?;
+        reporter.info(&format!(
+            "{} costs {} base units of {}, paid to {}",
+            config.path,
+            requirements.max_amount_required,
+            truncate_pubkey(&requirements.asset),
+            truncate_pubkey(&requirements.pay_to)
+        ));
+        routes.insert(
+            config.path.clone(),
+            Route { config: config.clone(), requirements },
+        );
+    }
+
+    let server = Arc::new(ResourceServer {
+        routes,
+        facilitator_url: facilitator_url.trim_end_matches('/').to_string(),
+        http,
+        reporter: reporter.clone(),
+    });
+
+    let app = Router::new().fallback(handle).with_state(server.clone());
+
+    let listener = tokio::net::TcpListener::bind(bind)
+        .await
+        .context(format!("Failed to bind resource server to {}", bind))?;
+
+    reporter.step_finished(&format!(
+        "Serving {} paywalled route(s) on http://{} (facilitator: {})",
+        server.routes.len(),
+        bind,
+        server.facilitator_url
+    ));
+    reporter.result(json!({
+        "command": "serve",
+        "result": "listening",
+        "address": bind,
+        "facilitator_url": server.facilitator_url,
+        "routes": server.routes.keys().collect::<Vec<_>>(),
+    }));
+
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// Build the payment requirements of one route
+async fn route_requirements(
+    client: &impl RpcApi,
+    manifest: &Manifest,
+    config: &RouteConfig,
+    bind: &str,
+    network: &str,
+    fee_payer: &Pubkey,
+) -> Result<PaymentRequirements> {
+    let mint =
+        match config.mint.clone().or_else(|| manifest.payments.mint.clone()) {
+            Some(mint) => mint,
+            None => env::var("XUSD_MINT").context(format!(
+            "Route {} has no mint. Set one in x402.toml or run 'xforth fund' \
+                first.",
+            config.path
+        ))?,
+        };
+    let mint = Pubkey::from_str(&mint)
+        .context(format!("Invalid mint for route {}", config.path))?;
+    let mint_account = client.get_account(&mint).await?.ok_or_else(|| {
+        Error::NotFound(format!(
+            "Mint {} of route {} not found on this cluster",
+            mint, config.path
+        ))
+    })?;
+    let mint = MintInfo::from_account(&mint, &mint_account)?;
+
+    let pay_to = match config
+        .pay_to
+        .clone()
+        .or_else(|| manifest.payments.pay_to.clone())
+    {
+        Some(pay_to) => Pubkey::from_str(&pay_to)
+            .context(format!("Invalid payTo for route {}", config.path))?,
+        None => load_pubkey("FACILITATOR_KEYPAIR")?,
+    };
+
+    let price = config
+        .price
+        .as_ref()
+        .or(manifest.payments.default_amount.as_ref())
+        .context(format!("Route {} has no price", config.path))?;
+    let amount = token::parse_ui_amount(price.as_str(), mint.decimals)
+        .context(format!("Invalid price for route {}", config.path))?;
+    if amount == 0 {
+        return Err(anyhow::anyhow!(
+            "Route {} must have a positive price",
+            config.path
+        ));
+    }
+
+    let requirements = PaymentRequirements {
+        scheme: SCHEME_EXACT.to_string(),
+        network: network.to_string(),
+        max_amount_required: amount.to_string(),
+        resource: format!("http://{}{}", bind, config.path),
+        description: config.description.clone(),
+        mime_type: config.mime_type.clone(),
+        output_schema: None,
+        pay_to: pay_to.to_string(),
+        max_timeout_seconds: MAX_TIMEOUT_SECONDS,
+        asset: mint.address.to_string(),
+        extra: Some(json!({ "feePayer": fee_payer.to_string() })),
+    };
+    requirements.validate()?;
+    Ok(requirements)
+}
+
+/// Ask the facilitator which key pays fees for `network`
+async fn fetch_fee_payer(
+    http: &reqwest::Client,
+    facilitator_url: &str,
+    network: &str,
+) -> Result<Pubkey> {
+    let url = format!("{}/supported", facilitator_url.trim_end_matches('/'));
+    let supported: SupportedResponse = http
+        .get(&url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .context(format!(
+            "Failed to reach facilitator at {}. Is 'xforth \
+            facilitator serve' running?",
+            url
+        ))?
+        .json()
+        .await
+        .context("Invalid /supported response from facilitator")?;
+
+    let kind = supported
+        .kinds
+        .iter()
+        .find(|kind| kind.scheme == SCHEME_EXACT && kind.network == network)
+        .context(format!(
+            "Facilitator does not support 'exact' payments on {}",
+            network
+        ))?;
+    x402::fee_payer_from_extra(kind.extra.as_ref())?
+        .context("Facilitator did not advertise a feePayer")
+}
+
+fn payment_required(
+    requirements: &PaymentRequirements,
+    error: impl Into<String>,
+) -> Response {
+    let body = PaymentRequiredResponse::new(
+        vec![requirements.clone()],
+        Some(error.into()),
+    );
+    (StatusCode::PAYMENT_REQUIRED, Json(body)).into_response()
+}
+
+async fn handle(
+    State(server): State<Arc<ResourceServer>>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    let Some(route) = server.routes.get(uri.path()) else {
+        return (StatusCode::NOT_FOUND, "Not found").into_response();
+    };
+    let requirements = &route.requirements;
+
+    let Some(header_value) = headers.get(PAYMENT_HEADER) else {
+        return payment_required(requirements, "X-PAYMENT header is required");
+    };
+    let payload = match header_value
+        .to_str()
+        .map_err(|e| e.to_string())
+        .and_then(|value| {
+            PaymentPayload::from_header(value).map_err(|e| e.to_string())
+        }) {
+        Ok(payload) => payload,
+        Err(err) => {
+            return payment_required(
+                requirements,
+                format!("Invalid X-PAYMENT header: {}", err),
+            )
+        }
+    };
+
+    let request = FacilitatorRequest::new(payload, requirements.clone());
+
+    let verification: VerifyResponse =
+        match server.call_facilitator("verify", &request).await {
+            Ok(verification) => verification,
+            Err(err) => {
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    format!("Facilitator unavailable: {}", err),
+                )
+                    .into_response()
+            }
+        };
+    if !verification.is_valid {
+        let reason = verification
+            .message
+            .or(verification.invalid_reason)
+            .unwrap_or_default();
+        return payment_required(
+            requirements,
+            format!("Payment rejected: {}", reason),
+        );
+    }
+
+    let settlement: SettlementResponse =
+        match server.call_facilitator("settle", &request).await {
+            Ok(settlement) => settlement,
+            Err(err) => {
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    format!("Facilitator unavailable: {}", err),
+                )
+                    .into_response()
+            }
+        };
+    if !settlement.success {
+        let reason = settlement.error_reason.unwrap_or_default();
+        return payment_required(
+            requirements,
+            format!("Settlement failed: {}", reason),
+        );
+    }
+
+    server.reporter.step_finished(&format!(
+        "Paid request for {} settled. Tx: {}",
+        uri.path(),
+        settlement.transaction
+    ));
+
+    let receipt = match settlement.to_header().map(HeaderValue::try_from) {
+        Ok(Ok(receipt)) => receipt,
+        _ => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to encode settlement",
+            )
+                .into_response()
+        }
+    };
+
+    let mut response = route.config.content.clone().into_response();
+    if let Ok(content_type) = HeaderValue::from_str(&route.config.mime_type) {
+        response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    response.headers_mut().insert(PAYMENT_RESPONSE_HEADER, receipt);
+    response
+}
+
+impl ResourceServer {
+    async fn call_facilitator<T: serde::de::DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        request: &FacilitatorRequest,
+    ) -> Result<T> {
+        let response = self
+            .http
+            .post(format!("{}/{}", self.facilitator_url, endpoint))
+            .json(request)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json().await?)
+    }
+}
@@ -1,102 +1,335 @@
-use solana_client::rpc_client::RpcClient;
+use crate::cluster::ClusterProfile;
+use crate::confirm::Confirmer;
+use crate::error::Error;
+use crate::guard;
+use crate::keystore::load_keypair;
+use crate::project::Project;
+use crate::report::{self, Reporter};
+use crate::retry::{RetryPolicy, RetryingRpc};
+use crate::rpc::RpcApi;
+use crate::token::{self, MintInfo};
+use anyhow::{Context, Result};
+use serde::Serialize;
 use solana_sdk::{
-    signature::Signer,
-    transaction::Transaction,
+    hash::Hash,
     native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
 };
-use solana_system_interface::instruction as system_instruction;
-use solana_commitment_config::CommitmentConfig;
-use anyhow::{Result, Context};
-use serde_json::json;
-use crate::utils::{log_action, log_info, load_keypair_from_env, log_balance, output_json};
+use std::env;
+use std::str::FromStr;
+
+/// xUSD sent from the Payer to the Facilitator by the test payment
+pub const TEST_XUSD_AMOUNT: u64 = 1;
 
-// Helper functions for SOL <-> lamports conversion
+/// Outcome of `xforth test`
+#[derive(Clone, Debug, Serialize)]
+pub struct TestReport {
+    pub transaction_signature: String,
+    pub mint: String,
+    pub token_program: String,
+    pub transfer_amount_xusd: u64,
+    /// Fee withheld by a Token-2022 mint, in whole xUSD
+    pub transfer_fee: f64,
+    pub fee_payer: String,
+    /// Whether the payment created the Facilitator's token account
+    pub created_token_account: bool,
+    /// SOL balances
+    pub payer_balance_before: f64,
+    pub payer_balance_after: f64,
+    pub facilitator_balance_before: f64,
+    pub facilitator_balance_after: f64,
+    /// xUSD balances, in whole tokens
+    pub payer_xusd_before: f64,
+    pub payer_xusd_after: f64,
+    pub facilitator_xusd_before: f64,
+    pub facilitator_xusd_after: f64,
+}
+
+// Helper function for lamports -> SOL conversion
 fn lamports_to_sol(lamports: u64) -> f64 {
     lamports as f64 / LAMPORTS_PER_SOL as f64
 }
 
-fn sol_to_lamports(sol: f64) -> u64 {
-    (sol * LAMPORTS_PER_SOL as f64) as u64
-}
+pub async fn run(
+    project: &Project,
+    cluster: &ClusterProfile,
+    confirmer: &Confirmer,
+    retry: &RetryPolicy,
+    reporter: &dyn Reporter,
+) -> Result<TestReport> {
+    project.load_env()?;
 
-pub async fn run(rpc_url: &str, json_output: bool) -> Result<()> {
-    // Load .env from current directory
-    dotenvy::dotenv().context("Failed to load .env file. Make sure you're in the project directory and have run 'xforth init' first.")?;
+    let client = RetryingRpc::new(cluster.client(), *retry);
 
-    if !json_output {
-        log_action("Testing x402 payment flow...");
+    let payer_keypair = load_keypair("PAYER_KEYPAIR")?;
+    let facilitator_keypair = load_keypair("FACILITATOR_KEYPAIR")?;
+
+    let mint_address = env::var("XUSD_MINT")
+        .map_err(|_| Error::EnvVarMissing("XUSD_MINT".to_string()))
+        .context("XUSD_MINT is not set. Run 'xforth fund' first.")?;
+    let mint_address = Pubkey::from_str(&mint_address).map_err(|_| {
+        Error::InvalidInput(format!(
+            "XUSD_MINT is not a valid public key: {}",
+            mint_address
+        ))
+    })?;
+
+    let report = run_with(
+        &client,
+        confirmer,
+        &payer_keypair,
+        &facilitator_keypair,
+        &mint_address,
+        reporter,
+    )
+    .await?;
+    if let Some(link) = cluster.explorer_link(&report.transaction_signature) {
+        reporter.info(&format!("Explorer: {}", link));
     }
+    reporter.result(report::success("test", &report));
 
-    let client = RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed());
+    Ok(report)
+}
 
-    let payer_keypair = load_keypair_from_env("PAYER_KEYPAIR")?;
-    let facilitator_keypair = load_keypair_from_env("FACILITATOR_KEYPAIR")?;
+/// Send the test payment through `client` and check the resulting balances.
+/// Unlike [`run`], it does not report the result.
+pub async fn run_with(
+    client: &impl RpcApi,
+    confirmer: &Confirmer,
+    payer_keypair: &Keypair,
+    facilitator_keypair: &Keypair,
+    mint_address: &Pubkey,
+    reporter: &dyn Reporter,
+) -> Result<TestReport> {
+    reporter.step_started("Testing x402 payment flow...");
 
-    // Check balances
-    if !json_output {
-        log_info("Checking wallet balances...");
-    }
+    let mint_account =
+        client.get_account(mint_address).await?.ok_or_else(|| {
+            Error::NotFound(
+                "xUSD mint not found. Run 'xforth fund' first.".to_string(),
+            )
+        })?;
+    let mint = MintInfo::from_account(mint_address, &mint_account)?;
 
-    let payer_balance_lamports = client.get_balance(&payer_keypair.pubkey())?;
-    let facilitator_balance_lamports = client.get_balance(&facilitator_keypair.pubkey())?;
+    let payer_token_account = token::associated_token_address(
+        &payer_keypair.pubkey(),
+        &mint.address,
+        mint.program,
+    );
+    let facilitator_token_account = token::associated_token_address(
+        &facilitator_keypair.pubkey(),
+        &mint.address,
+        mint.program,
+    );
+
+    // Check balances
+    reporter.info("Checking wallet balances...");
 
-    let payer_balance = lamports_to_sol(payer_balance_lamports);
+    let facilitator_balance_lamports =
+        client.get_balance(&facilitator_keypair.pubkey()).await?;
+    let payer_balance =
+        lamports_to_sol(client.get_balance(&payer_keypair.pubkey()).await?);
     let facilitator_balance = lamports_to_sol(facilitator_balance_lamports);
+    let payer_tokens =
+        token::token_balance(client, &payer_token_account).await?;
+    let facilitator_tokens =
+        token::token_balance(client, &facilitator_token_account).await?;
 
-    if !json_output {
-        log_balance("Payer balance", payer_balance);
-        log_balance("Facilitator balance", facilitator_balance);
-    }
+    reporter.balance("Payer balance", payer_balance, "SOL");
+    reporter.balance(
+        "Payer balance",
+        token::to_ui_amount(payer_tokens, mint.decimals),
+        "xUSD",
+    );
+    reporter.balance("Facilitator balance", facilitator_balance, "SOL");
+    reporter.balance(
+        "Facilitator balance",
+        token::to_ui_amount(facilitator_tokens, mint.decimals),
+        "xUSD",
+    );
 
     // Validate balances
-    if payer_balance < 0.1 {
-        return Err(anyhow::anyhow!(
-            "Insufficient payer balance: {} SOL. Run 'xforth fund' first.",
-            payer_balance
-        ));
+    let amount = token::to_base_units(TEST_XUSD_AMOUNT, mint.decimals)
+        .context("Test amount is too large")?;
+    if payer_tokens < amount {
+        return Err(Error::InsufficientBalance(format!(
+            "Insufficient payer balance: {} xUSD. Run 'xforth fund' first.",
+            token::to_ui_amount(payer_tokens, mint.decimals)
+        ))
+        .into());
     }
 
-    // Execute test payment (transfer 0.1 SOL from payer to facilitator)
-    if !json_output {
-        log_action("Executing test payment...");
+    // Execute test payment (transfer xUSD from the payer's to the
+    // facilitator's token account)
+    reporter.step_started("Executing test payment...");
+
+    let created_token_account =
+        client.get_account(&facilitator_token_account).await?.is_none();
+    let recent_blockhash = client.get_latest_blockhash().await?;
+    let mut tx = payment_transaction(
+        payer_keypair,
+        facilitator_keypair,
+        &mint,
+        amount,
+        created_token_account,
+        recent_blockhash,
+    )?;
+
+    // The payer funds the facilitator's token account, the facilitator pays
+    // the fee
+    let fee = guard::fee(&tx);
+    let rent = guard::estimate_cost(client, &tx).await? - fee;
+    let payer_lamports = client.get_balance(&payer_keypair.pubkey()).await?;
+    if payer_lamports < rent {
+        return Err(Error::InsufficientBalance(format!(
+            "PAYER_KEYPAIR holds {} SOL but needs {} SOL for the \
+                Facilitator's token account rent. Run 'xforth fund' first.",
+            lamports_to_sol(payer_lamports),
+            lamports_to_sol(rent)
+        ))
+        .into());
+    }
+    if facilitator_balance_lamports < fee {
+        return Err(Error::InsufficientBalance(format!(
+            "FACILITATOR_KEYPAIR holds {} SOL but needs {} SOL for the \
+                transaction fee. Run 'xforth fund' first.",
+            lamports_to_sol(facilitator_balance_lamports),
+            lamports_to_sol(fee)
+        ))
+        .into());
     }
 
-    let transfer_amount = 0.1;
-    let instruction = system_instruction::transfer(
-        &payer_keypair.pubkey(),
-        &facilitator_keypair.pubkey(),
-        sol_to_lamports(transfer_amount),
+    let sig = confirmer
+        .send_and_confirm(
+            client,
+            &mut tx,
+            &[payer_keypair, facilitator_keypair],
+            "Test payment",
+            reporter,
+        )
+        .await?;
+
+    // Get updated balances
+    let payer_balance_after =
+        lamports_to_sol(client.get_balance(&payer_keypair.pubkey()).await?);
+    let facilitator_balance_after = lamports_to_sol(
+        client.get_balance(&facilitator_keypair.pubkey()).await?,
     );
+    let payer_tokens_after =
+        token::token_balance(client, &payer_token_account).await?;
+    let facilitator_tokens_after =
+        token::token_balance(client, &facilitator_token_account).await?;
 
-    let recent_blockhash = client.get_latest_blockhash()?;
-    let tx = Transaction::new_signed_with_payer(
-        &[instruction],
-        Some(&payer_keypair.pubkey()),
-        &[&payer_keypair],
-        recent_blockhash,
+    // Token-2022 mints may withhold a transfer fee from the recipient
+    let transfer_fee =
+        mint.transfer_fee.map(|fee| fee.fee_for(amount)).unwrap_or(0);
+    if payer_tokens_after + amount != payer_tokens
+        || facilitator_tokens + amount - transfer_fee
+            != facilitator_tokens_after
+    {
+        return Err(anyhow::anyhow!(
+            "Unexpected token balances after test payment (payer: {} -> {}, \
+                facilitator: {} -> {}). Tx: {}",
+            payer_tokens,
+            payer_tokens_after,
+            facilitator_tokens,
+            facilitator_tokens_after,
+            sig
+        ));
+    }
+
+    reporter.info(&format!("Payment successful! Tx: {}", sig));
+    reporter.balance("Payer balance", payer_balance_after, "SOL");
+    reporter.balance(
+        "Payer balance",
+        token::to_ui_amount(payer_tokens_after, mint.decimals),
+        "xUSD",
+    );
+    reporter.balance("Facilitator balance", facilitator_balance_after, "SOL");
+    reporter.balance(
+        "Facilitator balance",
+        token::to_ui_amount(facilitator_tokens_after, mint.decimals),
+        "xUSD",
     );
+    reporter.info("All tests passed! Your x402 setup is ready to use.");
+    let report = TestReport {
+        transaction_signature: sig.to_string(),
+        mint: mint.address.to_string(),
+        token_program: mint.program.name().to_string(),
+        transfer_amount_xusd: TEST_XUSD_AMOUNT,
+        transfer_fee: token::to_ui_amount(transfer_fee, mint.decimals),
+        fee_payer: facilitator_keypair.pubkey().to_string(),
+        created_token_account,
+        payer_balance_before: payer_balance,
+        payer_balance_after,
+        facilitator_balance_before: facilitator_balance,
+        facilitator_balance_after,
+        payer_xusd_before: token::to_ui_amount(payer_tokens, mint.decimals),
+        payer_xusd_after: token::to_ui_amount(
+            payer_tokens_after,
+            mint.decimals,
+        ),
+        facilitator_xusd_before: token::to_ui_amount(
+            facilitator_tokens,
+            mint.decimals,
+        ),
+        facilitator_xusd_after: token::to_ui_amount(
+            facilitator_tokens_after,
+            mint.decimals,
+        ),
+    };
 
-    let sig = client.send_and_confirm_transaction(&tx)?;
+    Ok(report)
+}
 
-    // Get updated balances
-    let payer_balance_after = lamports_to_sol(client.get_balance(&payer_keypair.pubkey())?);
-    let facilitator_balance_after = lamports_to_sol(client.get_balance(&facilitator_keypair.pubkey())?);
-
-    if json_output {
-        output_json(&json!({
-            "command": "test",
-            "result": "success",
-            "transaction_signature": sig.to_string(),
-            "transfer_amount_sol": transfer_amount,
-            "payer_balance_before": payer_balance,
-            "payer_balance_after": payer_balance_after,
-            "facilitator_balance_before": facilitator_balance,
-            "facilitator_balance_after": facilitator_balance_after,
-        }));
-    } else {
-        log_info(&format!("Payment successful! Tx: {}", sig));
-        log_info("All tests passed! Your x402 setup is ready to use.");
+/// Build the test payment: a `transfer_checked` of `amount` from the Payer's
+/// to the Facilitator's token account, creating the latter if needed.
+///
+/// The facilitator sponsors the fee: the payer signs first as the token
+/// authority, then the facilitator adds its fee-payer signature as it would at
+/// settle.
+pub fn payment_transaction(
+    payer: &Keypair,
+    facilitator: &Keypair,
+    mint: &MintInfo,
+    amount: u64,
+    create_token_account: bool,
+    recent_blockhash: Hash,
+) -> Result<Transaction> {
+    let mut instructions = Vec::new();
+    if create_token_account {
+        instructions.push(token::create_associated_token_account_ix(
+            &payer.pubkey(),
+            &facilitator.pubkey(),
+            &mint.address,
+            mint.program,
+        ));
     }
+    instructions.push(token::transfer_checked_ix(
+        mint.program,
+        &token::associated_token_address(
+            &payer.pubkey(),
+            &mint.address,
+            mint.program,
+        ),
+        &mint.address,
+        &token::associated_token_address(
+            &facilitator.pubkey(),
+            &mint.address,
+            mint.program,
+        ),
+        &payer.pubkey(),
+        amount,
+        mint.decimals,
+    )?);
 
-    Ok(())
-}
\ No newline at end of file
+    let mut tx = Transaction::new_with_payer(
+        &instructions,
+        Some(&facilitator.pubkey()),
+    );
+    tx.try_partial_sign(&[payer], recent_blockhash)?;
+    tx.try_partial_sign(&[facilitator], recent_blockhash)?;
+    Ok(tx)
+}
@@ -0,0 +1,390 @@
+use crate::error::Error;
+use crate::rpc::RpcApi;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use solana_sdk::{
+    account::Account, instruction::Instruction, program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_system_interface::instruction as system_instruction;
+use spl_associated_token_account_interface::{
+    address::get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022_interface::{
+    extension::{
+        metadata_pointer, transfer_fee, BaseStateWithExtensions,
+        ExtensionType, StateWithExtensions,
+    },
+    instruction as token_instruction,
+    state::{Account as TokenAccount, Mint},
+};
+use spl_token_metadata_interface::state::TokenMetadata;
+
+/// Token program used to create the xUSD mint
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TokenProgram {
+    /// Classic SPL Token program
+    #[default]
+    #[value(name = "spl-token")]
+    SplToken,
+    /// SPL Token-2022 with transfer-fee and metadata extensions
+    #[value(name = "token-2022")]
+    Token2022,
+}
+
+impl TokenProgram {
+    /// Program id of the token program
+    pub fn id(&self) -> Pubkey {
+        match self {
+            TokenProgram::SplToken => spl_token::id(),
+            TokenProgram::Token2022 => spl_token_2022_interface::id(),
+        }
+    }
+
+    /// Detect the token program from the owner of a mint or token account
+    pub fn from_owner(owner: &Pubkey) -> Option<Self> {
+        if *owner == spl_token::id() {
+            Some(TokenProgram::SplToken)
+        } else if *owner == spl_token_2022_interface::id() {
+            Some(TokenProgram::Token2022)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenProgram::SplToken => "spl-token",
+            TokenProgram::Token2022 => "token-2022",
+        }
+    }
+}
+
+/// TransferFeeConfig extension parameters (Token-2022 only)
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct TransferFee {
+    /// Fee charged on every transfer, in basis points
+    pub basis_points: u16,
+    /// Upper bound on the fee, in base units
+    pub maximum_fee: u64,
+}
+
+impl TransferFee {
+    /// Fee withheld from a transfer of `amount` base units, rounded up as
+    /// on-chain
+    pub fn fee_for(&self, amount: u64) -> u64 {
+        let fee =
+            (amount as u128 * self.basis_points as u128).div_ceil(10_000);
+        (fee as u64).min(self.maximum_fee)
+    }
+}
+
+/// On-mint TokenMetadata extension fields (Token-2022 only)
+#[derive(Clone, Debug)]
+pub struct MetadataFields {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+/// Everything needed to create and initialize a new mint account
+pub struct MintSpec<'a> {
+    pub program: TokenProgram,
+    pub mint: &'a Pubkey,
+    pub mint_authority: &'a Pubkey,
+    pub decimals: u8,
+    pub transfer_fee: Option<TransferFee>,
+    /// Authority allowed to withdraw withheld transfer fees
+    pub withdraw_authority: &'a Pubkey,
+    pub metadata: Option<&'a MetadataFields>,
+}
+
+impl MintSpec<'_> {
+    /// Returns `(space, rent_len)` for the mint account.
+    ///
+    /// `space` is what `create_account` allocates. The TokenMetadata extension
+    /// is variable length and reallocated by the token program when it is
+    /// initialized, so `rent_len` also covers it and the account must be
+    /// funded for `rent_len` bytes up front.
+    pub fn account_len(&self) -> Result<(usize, usize)> {
+        match self.program {
+            TokenProgram::SplToken => {
+                Ok((spl_token::state::Mint::LEN, spl_token::state::Mint::LEN))
+            }
+            TokenProgram::Token2022 => {
+                let mut extensions = Vec::new();
+                if self.transfer_fee.is_some() {
+                    extensions.push(ExtensionType::TransferFeeConfig);
+                }
+                if self.metadata.is_some() {
+                    extensions.push(ExtensionType::MetadataPointer);
+                }
+                let space = ExtensionType::try_calculate_account_len::<Mint>(
+                    &extensions,
+                )?;
+
+                let metadata_len = match self.metadata {
+                    Some(fields) => TokenMetadata {
+                        mint: *self.mint,
+                        name: fields.name.clone(),
+                        symbol: fields.symbol.clone(),
+                        uri: fields.uri.clone(),
+                        ..Default::default()
+                    }
+                    .tlv_size_of()?,
+                    None => 0,
+                };
+
+                Ok((space, space + metadata_len))
+            }
+        }
+    }
+
+    /// Build the instructions creating and initializing the mint.
+    ///
+    /// Extension initializers must run before `initialize_mint`, and the
+    /// metadata itself can only be written once the mint is initialized.
+    pub fn instructions(
+        &self,
+        funder: &Pubkey,
+        lamports: u64,
+    ) -> Result<Vec<Instruction>> {
+        let program_id = self.program.id();
+        let (space, _) = self.account_len()?;
+
+        let mut instructions = vec![system_instruction::create_account(
+            funder,
+            self.mint,
+            lamports,
+            space as u64,
+            &program_id,
+        )];
+
+        if self.program == TokenProgram::Token2022 {
+            if let Some(fee) = self.transfer_fee {
+                instructions.push(
+                    transfer_fee::instruction::initialize_transfer_fee_config(
+                        &program_id,
+                        self.mint,
+                        Some(self.mint_authority),
+                        Some(self.withdraw_authority),
+                        fee.basis_points,
+                        fee.maximum_fee,
+                    )?,
+                );
+            }
+            if self.metadata.is_some() {
+                instructions.push(metadata_pointer::instruction::initialize(
+                    &program_id,
+                    self.mint,
+                    Some(*self.mint_authority),
+                    Some(*self.mint),
+                )?);
+            }
+        }
+
+        instructions.push(token_instruction::initialize_mint(
+            &program_id,
+            self.mint,
+            self.mint_authority,
+            None,
+            self.decimals,
+        )?);
+
+        if let (TokenProgram::Token2022, Some(fields)) =
+            (self.program, self.metadata)
+        {
+            instructions.push(
+                spl_token_metadata_interface::instruction::initialize(
+                    &program_id,
+                    self.mint,
+                    self.mint_authority,
+                    self.mint,
+                    self.mint_authority,
+                    fields.name.clone(),
+                    fields.symbol.clone(),
+                    fields.uri.clone(),
+                ),
+            );
+        }
+
+        Ok(instructions)
+    }
+}
+
+/// On-chain state of an existing mint
+#[derive(Clone, Copy, Debug)]
+pub struct MintInfo {
+    pub address: Pubkey,
+    pub program: TokenProgram,
+    pub decimals: u8,
+    pub mint_authority: Option<Pubkey>,
+    pub transfer_fee: Option<TransferFee>,
+}
+
+impl MintInfo {
+    /// Parse a mint account owned by either token program
+    pub fn from_account(address: &Pubkey, account: &Account) -> Result<Self> {
+        let program = TokenProgram::from_owner(&account.owner).context(
+            format!("Account {} is not owned by a token program", address),
+        )?;
+        let mint = StateWithExtensions::<Mint>::unpack(&account.data)
+            .context(format!("Account {} is not a valid mint", address))?;
+
+        let transfer_fee = mint
+            .get_extension::<transfer_fee::TransferFeeConfig>()
+            .ok()
+            .map(|config| {
+                let fee = config.newer_transfer_fee;
+                TransferFee {
+                    basis_points: u16::from(fee.transfer_fee_basis_points),
+                    maximum_fee: u64::from(fee.maximum_fee),
+                }
+            });
+
+        Ok(Self {
+            address: *address,
+            program,
+            decimals: mint.base.decimals,
+            mint_authority: mint.base.mint_authority.into(),
+            transfer_fee,
+        })
+    }
+}
+
+/// Amount held by a token account of either token program
+pub fn token_account_amount(
+    address: &Pubkey,
+    account: &Account,
+) -> Result<u64> {
+    let state =
+        StateWithExtensions::<TokenAccount>::unpack(&account.data).context(
+            format!("Account {} is not a valid token account", address),
+        )?;
+    Ok(state.base.amount)
+}
+
+/// Read the balance of a token account, treating a missing account as empty
+pub async fn token_balance(
+    client: &impl RpcApi,
+    token_account: &Pubkey,
+) -> Result<u64> {
+    match client.get_account(token_account).await? {
+        Some(account) => token_account_amount(token_account, &account),
+        None => Ok(0),
+    }
+}
+
+/// Associated token account of `wallet` for `mint`
+pub fn associated_token_address(
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    program: TokenProgram,
+) -> Pubkey {
+    get_associated_token_address_with_program_id(wallet, mint, &program.id())
+}
+
+/// Idempotently create the associated token account of `wallet` for `mint`
+pub fn create_associated_token_account_ix(
+    funder: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    program: TokenProgram,
+) -> Instruction {
+    create_associated_token_account_idempotent(
+        funder,
+        wallet,
+        mint,
+        &program.id(),
+    )
+}
+
+/// `mint_to_checked` for either token program
+pub fn mint_to_ix(
+    program: TokenProgram,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+    decimals: u8,
+) -> Result<Instruction> {
+    Ok(token_instruction::mint_to_checked(
+        &program.id(),
+        mint,
+        destination,
+        authority,
+        &[],
+        amount,
+        decimals,
+    )?)
+}
+
+/// `transfer_checked` for either token program
+pub fn transfer_checked_ix(
+    program: TokenProgram,
+    source: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+    decimals: u8,
+) -> Result<Instruction> {
+    Ok(token_instruction::transfer_checked(
+        &program.id(),
+        source,
+        mint,
+        destination,
+        authority,
+        &[],
+        amount,
+        decimals,
+    )?)
+}
+
+/// Convert base units into a UI amount
+pub fn to_ui_amount(base_units: u64, decimals: u8) -> f64 {
+    base_units as f64 / 10_f64.powi(decimals as i32)
+}
+
+/// Convert a whole-token amount into base units
+pub fn to_base_units(amount: u64, decimals: u8) -> Option<u64> {
+    amount.checked_mul(10_u64.checked_pow(decimals as u32)?)
+}
+
+/// Convert a decimal amount of tokens, e.g. `"0.01"`, into base units
+/// exactly: more fractional digits than `decimals` is an error, not rounded
+pub fn parse_ui_amount(amount: &str, decimals: u8) -> Result<u64, Error> {
+    let invalid = |reason: &str| {
+        Error::InvalidInput(format!("Invalid amount '{}': {}", amount, reason))
+    };
+    let (whole, fraction) =
+        amount.trim().split_once('.').unwrap_or((amount.trim(), ""));
+    if whole.is_empty() && fraction.is_empty()
+        || ![whole, fraction]
+            .iter()
+            .all(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return Err(invalid("expected a decimal number"));
+    }
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > decimals as usize {
+        return Err(invalid(&format!(
+            "the token has only {} decimals",
+            decimals
+        )));
+    }
+
+    let digits = format!(
+        "{}{}{}",
+        whole,
+        fraction,
+        "0".repeat(decimals as usize - fraction.len())
+    );
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    digits.parse().map_err(|_| invalid("too large"))
+}
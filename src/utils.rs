@@ -1,7 +1,8 @@
-use solana_sdk::signature::Keypair;
-use anyhow::{Result, Context};
+use anyhow::{Context, Result};
 use colored::Colorize;
-use std::env;
+use solana_sdk::signature::Keypair;
+
+pub mod env_file;
 
 /// Generate a new Solana keypair
 pub fn generate_keypair() -> Keypair {
@@ -22,7 +23,10 @@ pub fn keypair_from_json(json_str: &str) -> Result<Keypair> {
     // Solana SDK 3.0: keypair is 64 bytes (secret key 32 + public key 32)
     // Use new_from_array which takes only the secret key (first 32 bytes)
     if bytes.len() != 64 {
-        return Err(anyhow::anyhow!("Invalid keypair bytes length: expected 64, got {}", bytes.len()));
+        return Err(anyhow::anyhow!(
+            "Invalid keypair bytes length: expected 64, got {}",
+            bytes.len()
+        ));
     }
 
     let mut secret_key = [0u8; 32];
@@ -30,13 +34,6 @@ pub fn keypair_from_json(json_str: &str) -> Result<Keypair> {
     Ok(Keypair::new_from_array(secret_key))
 }
 
-/// Load keypair from environment variable
-pub fn load_keypair_from_env(env_var: &str) -> Result<Keypair> {
-    let json_str = env::var(env_var)
-        .context(format!("Environment variable {} not found", env_var))?;
-    keypair_from_json(&json_str)
-}
-
 /// Logging functions with colored output
 pub fn log_action(msg: &str) {
     println!("{} {}", "Action:".green().bold(), msg);
@@ -46,7 +43,10 @@ pub fn log_info(msg: &str) {
     println!("{} \"{}\"", "Log:".yellow(), msg);
 }
 
-#[allow(dead_code)]
+pub fn log_warning(msg: &str) {
+    println!("{} {}", "Warning:".yellow().bold(), msg);
+}
+
 pub fn log_error(msg: &str) {
     eprintln!("{} {}", "Error:".red().bold(), msg);
 }
@@ -55,6 +55,10 @@ pub fn log_balance(label: &str, balance: f64) {
     println!("{}: {} SOL", label, balance);
 }
 
+pub fn log_token_balance(label: &str, balance: f64, symbol: &str) {
+    println!("{}: {} {}", label, balance, symbol);
+}
+
 /// JSON output for programmatic consumption
 pub fn output_json(data: &serde_json::Value) {
     println!("{}", serde_json::to_string_pretty(data).unwrap());
@@ -63,8 +67,8 @@ pub fn output_json(data: &serde_json::Value) {
 /// Truncate public key for display (first 4 chars...last 4 chars)
 pub fn truncate_pubkey(pubkey: &str) -> String {
     if pubkey.len() > 8 {
-        format!("{}...{}", &pubkey[..4], &pubkey[pubkey.len()-4..])
+        format!("{}...{}", &pubkey[..4], &pubkey[pubkey.len() - 4..])
     } else {
         pubkey.to_string()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+enum Line {
+    /// `KEY=value` assignment, with the line as originally written
+    Entry { key: String, value: String, raw: String },
+    /// Comment, blank line or anything else that is kept verbatim
+    Other(String),
+}
+
+/// An editable `.env` file that keeps comments, blank lines and key order
+/// intact
+#[derive(Clone, Debug)]
+pub struct EnvFile {
+    path: PathBuf,
+    lines: Vec<Line>,
+}
+
+impl EnvFile {
+    /// Load an env file, starting empty if it does not exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                String::new()
+            }
+            Err(err) => {
+                return Err(err)
+                    .context(format!("Failed to read {}", path.display()))
+            }
+        };
+        Ok(Self::parse(path, &content))
+    }
+
+    /// An env file with no path, which `save` leaves in memory
+    pub fn in_memory() -> Self {
+        Self::parse(PathBuf::new(), "")
+    }
+
+    fn parse(path: PathBuf, content: &str) -> Self {
+        let lines = content.lines().map(parse_line).collect();
+        Self { path, lines }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Value of `key`, if present. The last assignment wins, as in dotenv.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().rev().find_map(|line| match line {
+            Line::Entry { key: k, value, .. } if k == key => {
+                Some(value.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    /// Iterate over all `(key, value)` pairs in file order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value, .. } => {
+                Some((key.as_str(), value.as_str()))
+            }
+            Line::Other(_) => None,
+        })
+    }
+
+    /// Insert or update `key`, keeping its position if it already exists
+    pub fn set(&mut self, key: &str, value: &str) {
+        let raw = format!("{}={}", key, quote(value));
+        let existing = self.lines.iter_mut().rev().find(
+            |line| matches!(line, Line::Entry { key: k, .. } if k == key),
+        );
+        let entry = Line::Entry {
+            key: key.to_string(),
+            value: value.to_string(),
+            raw,
+        };
+        match existing {
+            Some(line) => *line = entry,
+            None => self.lines.push(entry),
+        }
+    }
+
+    /// Remove every assignment of `key`, returning the last value
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let value = self.get(key).map(str::to_string);
+        self.lines.retain(
+            |line| !matches!(line, Line::Entry { key: k, .. } if k == key),
+        );
+        value
+    }
+
+    /// Write the file back to disk. It holds private keys, so it is written
+    /// owner-only to a temporary file first and renamed over the original,
+    /// leaving the old file intact if writing fails. An in-memory file is
+    /// left as is.
+    pub fn save(&self) -> Result<()> {
+        if self.path.as_os_str().is_empty() {
+            return Ok(());
+        }
+
+        let mut content = String::new();
+        for line in &self.lines {
+            match line {
+                Line::Entry { raw, .. } => content.push_str(raw),
+                Line::Other(raw) => content.push_str(raw),
+            }
+            content.push('\n');
+        }
+
+        let file_name = self.path.file_name().context(format!(
+            "Invalid env file path {}",
+            self.path.display()
+        ))?;
+        let mut temp_name = OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(".tmp");
+        let temp_path = self.path.with_file_name(temp_name);
+
+        write_private(&temp_path, content.as_bytes())
+            .and_then(|()| fs::rename(&temp_path, &self.path))
+            .inspect_err(|_| {
+                fs::remove_file(&temp_path).ok();
+            })
+            .context(format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// Write `content` to a file readable only by its owner. A new file is
+/// created with mode 0600; an existing one is tightened before writing.
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+fn parse_line(raw: &str) -> Line {
+    let trimmed = raw.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Line::Other(raw.to_string());
+    }
+
+    let assignment = trimmed.strip_prefix("export ").unwrap_or(trimmed);
+    let Some((key, value)) = assignment.split_once('=') else {
+        return Line::Other(raw.to_string());
+    };
+
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Line::Other(raw.to_string());
+    }
+
+    Line::Entry {
+        key: key.to_string(),
+        value: unquote(value.trim()),
+        raw: raw.to_string(),
+    }
+}
+
+/// Value of an assignment as dotenv reads it: single quotes are literal,
+/// double quotes take backslash escapes, and unquoted values end at an
+/// inline comment
+fn unquote(value: &str) -> String {
+    if let Some(rest) = value.strip_prefix('\'') {
+        if let Some(end) = rest.find('\'') {
+            return rest[..end].to_string();
+        }
+    }
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut unquoted = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return unquoted,
+                '\\' => match chars.next() {
+                    Some('n') => unquoted.push('\n'),
+                    Some(c @ ('\\' | '"' | '\'' | '$')) => unquoted.push(c),
+                    Some(c) => {
+                        unquoted.push('\\');
+                        unquoted.push(c);
+                    }
+                    None => unquoted.push('\\'),
+                },
+                c => unquoted.push(c),
+            }
+        }
+    }
+    // Strip trailing inline comments from unquoted values
+    match value.find(" #") {
+        Some(index) => value[..index].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// Quote `value` so that dotenv reads it back unchanged. Single quotes are
+/// preferred since nothing inside them is expanded; values containing a
+/// single quote or a newline are double-quoted and escaped instead.
+fn quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value.chars().all(|c| {
+            c.is_ascii_alphanumeric() || "-_.,:/+=@[]{}%".contains(c)
+        });
+    if plain {
+        value.to_string()
+    } else if !value.contains(['\'', '\n']) {
+        format!("'{}'", value)
+    } else {
+        let mut quoted = String::from('"');
+        for c in value.chars() {
+            match c {
+                '\n' => quoted.push_str("\\n"),
+                '\\' | '"' | '$' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+}
@@ -0,0 +1,387 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use solana_sdk::{
+    instruction::Instruction, pubkey::Pubkey, transaction::Transaction,
+};
+use std::str::FromStr;
+
+/// x402 protocol version spoken by xforth
+pub const X402_VERSION: u8 = 1;
+
+/// The only payment scheme supported on Solana
+pub const SCHEME_EXACT: &str = "exact";
+
+/// Header carrying the base64 JSON [`PaymentPayload`]
+pub const PAYMENT_HEADER: &str = "X-PAYMENT";
+
+/// Header carrying the base64 JSON [`SettlementResponse`]
+pub const PAYMENT_RESPONSE_HEADER: &str = "X-PAYMENT-RESPONSE";
+
+/// SPL Memo program, used to attach a nonce to each payment
+pub const MEMO_PROGRAM_ID: Pubkey =
+    Pubkey::from_str_const("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+/// Longest payment nonce accepted in a memo
+pub const MAX_NONCE_LEN: usize = 64;
+
+/// Solana networks understood by the "exact" scheme
+pub const SUPPORTED_NETWORKS: &[&str] =
+    &["solana", "solana-devnet", "solana-testnet", "solana-localnet"];
+
+/// Errors raised while decoding or validating x402 messages
+#[derive(Debug, thiserror::Error)]
+pub enum X402Error {
+    #[error("invalid base64 in x402 header: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("invalid x402 JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid transaction encoding: {0}")]
+    Transaction(#[from] bincode::Error),
+    #[error("unsupported x402 version {0}, expected {X402_VERSION}")]
+    UnsupportedVersion(u8),
+    #[error("unsupported payment scheme '{0}'")]
+    UnsupportedScheme(String),
+    #[error("unsupported network '{0}'")]
+    UnsupportedNetwork(String),
+    #[error("invalid {field}: {reason}")]
+    InvalidField { field: &'static str, reason: String },
+}
+
+/// What a resource server accepts as payment for one resource
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRequirements {
+    pub scheme: String,
+    pub network: String,
+    /// Amount in the asset's base units, as a decimal string
+    pub max_amount_required: String,
+    pub resource: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
+    pub pay_to: String,
+    pub max_timeout_seconds: u64,
+    /// Mint address of the SPL token to pay with
+    pub asset: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<serde_json::Value>,
+}
+
+impl PaymentRequirements {
+    /// Check the scheme, network, amount and addresses
+    pub fn validate(&self) -> Result<(), X402Error> {
+        check_scheme(&self.scheme)?;
+        check_network(&self.network)?;
+        self.amount()?;
+        self.pay_to()?;
+        self.asset()?;
+        self.fee_payer()?;
+        Ok(())
+    }
+
+    /// Required amount in base units
+    pub fn amount(&self) -> Result<u64, X402Error> {
+        match self.max_amount_required.parse::<u64>() {
+            Ok(0) => {
+                Err(invalid("maxAmountRequired", "must be greater than zero"))
+            }
+            Ok(amount) => Ok(amount),
+            Err(err) => Err(invalid("maxAmountRequired", err)),
+        }
+    }
+
+    pub fn pay_to(&self) -> Result<Pubkey, X402Error> {
+        Pubkey::from_str(&self.pay_to).map_err(|err| invalid("payTo", err))
+    }
+
+    pub fn asset(&self) -> Result<Pubkey, X402Error> {
+        Pubkey::from_str(&self.asset).map_err(|err| invalid("asset", err))
+    }
+
+    /// Fee payer advertised by the facilitator in `extra.feePayer`, if any
+    pub fn fee_payer(&self) -> Result<Option<Pubkey>, X402Error> {
+        fee_payer_from_extra(self.extra.as_ref())
+    }
+}
+
+/// Body of a `402 Payment Required` response
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRequiredResponse {
+    pub x402_version: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub accepts: Vec<PaymentRequirements>,
+}
+
+/// Read `feePayer` from an `extra` object
+pub fn fee_payer_from_extra(
+    extra: Option<&serde_json::Value>,
+) -> Result<Option<Pubkey>, X402Error> {
+    match extra.and_then(|extra| extra.get("feePayer")) {
+        None => Ok(None),
+        Some(serde_json::Value::String(fee_payer)) => {
+            Pubkey::from_str(fee_payer)
+                .map(Some)
+                .map_err(|err| invalid("extra.feePayer", err))
+        }
+        Some(_) => Err(invalid("extra.feePayer", "must be a string")),
+    }
+}
+
+impl PaymentRequiredResponse {
+    pub fn new(
+        accepts: Vec<PaymentRequirements>,
+        error: Option<String>,
+    ) -> Self {
+        Self { x402_version: X402_VERSION, error, accepts }
+    }
+
+    /// First valid Solana "exact" requirement offered by the server
+    pub fn select(&self) -> Result<&PaymentRequirements, X402Error> {
+        check_version(self.x402_version)?;
+        let mut last_error = None;
+        for requirements in &self.accepts {
+            match requirements.validate() {
+                Ok(()) => return Ok(requirements),
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            invalid("accepts", "no payment requirements offered")
+        }))
+    }
+}
+
+/// Scheme-specific payload of an "exact" Solana payment
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ExactSvmPayload {
+    /// Base64 bincode-serialized, signed transaction
+    pub transaction: String,
+}
+
+/// Payment sent by the client in the `X-PAYMENT` header
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PaymentPayload {
+    pub x402_version: u8,
+    pub scheme: String,
+    pub network: String,
+    pub payload: ExactSvmPayload,
+}
+
+impl PaymentPayload {
+    /// Wrap a signed transaction paying `requirements`
+    pub fn new(
+        requirements: &PaymentRequirements,
+        transaction: &Transaction,
+    ) -> Result<Self, X402Error> {
+        Ok(Self {
+            x402_version: X402_VERSION,
+            scheme: requirements.scheme.clone(),
+            network: requirements.network.clone(),
+            payload: ExactSvmPayload {
+                transaction: BASE64.encode(bincode::serialize(transaction)?),
+            },
+        })
+    }
+
+    /// Decode and validate an `X-PAYMENT` header value
+    pub fn from_header(value: &str) -> Result<Self, X402Error> {
+        let payload: Self = decode_header(value)?;
+        payload.validate()?;
+        Ok(payload)
+    }
+
+    pub fn to_header(&self) -> Result<String, X402Error> {
+        encode_header(self)
+    }
+
+    pub fn validate(&self) -> Result<(), X402Error> {
+        check_version(self.x402_version)?;
+        check_scheme(&self.scheme)?;
+        check_network(&self.network)?;
+        self.transaction()?;
+        Ok(())
+    }
+
+    /// Check that the payload answers `requirements`
+    pub fn matches(
+        &self,
+        requirements: &PaymentRequirements,
+    ) -> Result<(), X402Error> {
+        if self.scheme != requirements.scheme {
+            return Err(invalid(
+                "scheme",
+                format!(
+                    "expected '{}', got '{}'",
+                    requirements.scheme, self.scheme
+                ),
+            ));
+        }
+        if self.network != requirements.network {
+            return Err(invalid(
+                "network",
+                format!(
+                    "expected '{}', got '{}'",
+                    requirements.network, self.network
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decode the signed transaction
+    pub fn transaction(&self) -> Result<Transaction, X402Error> {
+        let bytes = BASE64.decode(&self.payload.transaction)?;
+        Ok(bincode::deserialize(&bytes)?)
+    }
+}
+
+/// Settlement receipt returned in the `X-PAYMENT-RESPONSE` header
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementResponse {
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_reason: Option<String>,
+    /// Signature of the settled transaction, empty on failure
+    #[serde(default)]
+    pub transaction: String,
+    pub network: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payer: Option<String>,
+}
+
+impl SettlementResponse {
+    pub fn from_header(value: &str) -> Result<Self, X402Error> {
+        let response: Self = decode_header(value)?;
+        check_network(&response.network)?;
+        Ok(response)
+    }
+
+    pub fn to_header(&self) -> Result<String, X402Error> {
+        encode_header(self)
+    }
+}
+
+/// Body of the facilitator's `/verify` and `/settle` requests
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FacilitatorRequest {
+    pub x402_version: u8,
+    pub payment_payload: PaymentPayload,
+    pub payment_requirements: PaymentRequirements,
+}
+
+impl FacilitatorRequest {
+    pub fn new(
+        payment_payload: PaymentPayload,
+        payment_requirements: PaymentRequirements,
+    ) -> Self {
+        Self {
+            x402_version: X402_VERSION,
+            payment_payload,
+            payment_requirements,
+        }
+    }
+
+    /// Validate both messages and check that they belong together
+    pub fn validate(&self) -> Result<(), X402Error> {
+        check_version(self.x402_version)?;
+        self.payment_payload.validate()?;
+        self.payment_requirements.validate()?;
+        self.payment_payload.matches(&self.payment_requirements)
+    }
+}
+
+/// Facilitator answer to `/verify`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyResponse {
+    pub is_valid: bool,
+    /// Stable reason code when the payment is rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invalid_reason: Option<String>,
+    /// Human readable detail for `invalid_reason`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payer: Option<String>,
+}
+
+/// One scheme/network pair a facilitator can settle
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupportedKind {
+    pub x402_version: u8,
+    pub scheme: String,
+    pub network: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<serde_json::Value>,
+}
+
+/// Facilitator answer to `/supported`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SupportedResponse {
+    pub kinds: Vec<SupportedKind>,
+}
+
+/// Encode a message as base64 JSON for an x402 header
+pub fn encode_header<T: Serialize>(value: &T) -> Result<String, X402Error> {
+    Ok(BASE64.encode(serde_json::to_vec(value)?))
+}
+
+/// Decode a base64 JSON x402 header
+pub fn decode_header<T: DeserializeOwned>(
+    value: &str,
+) -> Result<T, X402Error> {
+    let bytes = BASE64.decode(value.trim())?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Random nonce that makes every payment transaction unique
+pub fn generate_nonce() -> String {
+    bs58::encode(rand::random::<[u8; 16]>()).into_string()
+}
+
+/// Memo instruction carrying a payment nonce
+pub fn nonce_memo_ix(nonce: &str) -> Instruction {
+    Instruction {
+        program_id: MEMO_PROGRAM_ID,
+        accounts: vec![],
+        data: nonce.as_bytes().to_vec(),
+    }
+}
+
+fn check_version(version: u8) -> Result<(), X402Error> {
+    if version == X402_VERSION {
+        Ok(())
+    } else {
+        Err(X402Error::UnsupportedVersion(version))
+    }
+}
+
+fn check_scheme(scheme: &str) -> Result<(), X402Error> {
+    if scheme == SCHEME_EXACT {
+        Ok(())
+    } else {
+        Err(X402Error::UnsupportedScheme(scheme.to_string()))
+    }
+}
+
+fn check_network(network: &str) -> Result<(), X402Error> {
+    if SUPPORTED_NETWORKS.contains(&network) {
+        Ok(())
+    } else {
+        Err(X402Error::UnsupportedNetwork(network.to_string()))
+    }
+}
+
+fn invalid(field: &'static str, reason: impl ToString) -> X402Error {
+    X402Error::InvalidField { field, reason: reason.to_string() }
+}
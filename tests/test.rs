@@ -1 +1,1810 @@
-// Tests will be added here
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use solana_sdk::hash::Hash;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_system_interface::instruction as system_instruction;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use xforth::cluster::{ClusterProfile, Commitment};
+use xforth::config::{Config, ConfigFlags, Source};
+use xforth::confirm::{self, Confirmer};
+use xforth::error;
+use xforth::fund::{self, FundOptions};
+use xforth::guard::{self, MainnetGuard, Network, SpendCaps, SpendGuard};
+use xforth::keys;
+use xforth::keystore;
+use xforth::manifest::Manifest;
+use xforth::mnemonic::{self, DerivationPath};
+use xforth::project::Project;
+use xforth::report::{ChannelReporter, Event, JsonReporter};
+use xforth::retry::{RetryPolicy, RetryingRpc};
+use xforth::rpc::{rpc_error, FakeRpc, RecordingRpc, RpcApi, RpcMethod};
+use xforth::test;
+use xforth::token;
+use xforth::utils::env_file::EnvFile;
+use xforth::utils::keypair_to_json;
+use xforth::x402::{
+    self, PaymentPayload, PaymentRequiredResponse, PaymentRequirements,
+    SettlementResponse, X402Error,
+};
+
+/// Default retry policy without the delays
+fn fast_retries() -> RetryPolicy {
+    RetryPolicy { base_delay: Duration::ZERO, ..Default::default() }
+}
+
+/// Error the retry policy treats as transient
+fn connection_reset() -> solana_rpc_client_api::client_error::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::ConnectionReset,
+        "connection reset by peer",
+    )
+    .into()
+}
+
+/// Directory in the temp directory, unique per test and removed on drop,
+/// even when the test fails
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let path = std::env::temp_dir()
+            .join(format!("xforth-{}", Keypair::new().pubkey()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+/// `.env` in `dir`
+fn temp_env_file(dir: &TempDir) -> EnvFile {
+    EnvFile::load(dir.join(".env")).unwrap()
+}
+
+#[tokio::test]
+async fn fund_stops_on_airdrop_error() {
+    let rpc =
+        RetryingRpc::new(RecordingRpc::new(FakeRpc::new()), fast_retries());
+    rpc.inner().fail_next(
+        RpcMethod::RequestAirdrop,
+        rpc_error("airdrop request failed: faucet is empty"),
+    );
+
+    let (payer, facilitator) = (Keypair::new(), Keypair::new());
+    let err = fund::run_with(
+        &rpc,
+        &Confirmer::default(),
+        &payer,
+        &facilitator,
+        &mut temp_env_file(&TempDir::new()),
+        &FundOptions::default(),
+        &JsonReporter,
+    )
+    .await
+    .unwrap_err();
+
+    // Not a transient error, so it is not retried
+    assert!(err.to_string().contains("Airdrop failed"), "{}", err);
+    assert_eq!(error::classify(&err), ("rpc_error", 5));
+    assert_eq!(rpc.inner().count(RpcMethod::RequestAirdrop), 1);
+    assert_eq!(rpc.inner().count(RpcMethod::SendTransaction), 0);
+}
+
+#[tokio::test]
+async fn retry_policy_retries_transient_errors_up_to_max_attempts() {
+    let policy = RetryPolicy { max_attempts: 3, ..fast_retries() };
+    let rpc = RetryingRpc::new(RecordingRpc::new(FakeRpc::new()), policy);
+    let payer = Keypair::new();
+    rpc.inner().inner().set_balance(&payer.pubkey(), 42);
+
+    rpc.inner().fail_next(RpcMethod::GetBalance, connection_reset());
+    rpc.inner().fail_next(RpcMethod::GetBalance, connection_reset());
+    assert_eq!(rpc.get_balance(&payer.pubkey()).await.unwrap(), 42);
+    assert_eq!(rpc.inner().count(RpcMethod::GetBalance), 3);
+
+    for _ in 0..3 {
+        rpc.inner()
+            .fail_next(RpcMethod::GetLatestBlockhash, connection_reset());
+    }
+    assert!(rpc.get_latest_blockhash().await.is_err());
+    assert_eq!(rpc.inner().count(RpcMethod::GetLatestBlockhash), 3);
+}
+
+#[test]
+fn retry_delays_back_off_exponentially() {
+    let policy = RetryPolicy {
+        base_delay: Duration::from_millis(100),
+        jitter: 0.0,
+        ..Default::default()
+    };
+    assert_eq!(policy.delay(1), Duration::from_millis(100));
+    assert_eq!(policy.delay(3), Duration::from_millis(400));
+    assert_eq!(policy.delay(30), xforth::retry::MAX_DELAY);
+
+    let jittered = RetryPolicy { jitter: 0.5, ..policy }.delay(2);
+    assert!(
+        jittered >= Duration::from_millis(100)
+            && jittered <= Duration::from_millis(300),
+        "{:?}",
+        jittered
+    );
+}
+
+#[tokio::test]
+async fn fund_reports_failed_airdrop_confirmation() {
+    let rpc = FakeRpc::new();
+    rpc.set_airdrop_status(Err(TransactionError::AccountNotFound));
+
+    let (payer, facilitator) = (Keypair::new(), Keypair::new());
+    let err = fund::run_with(
+        &rpc,
+        &Confirmer::default(),
+        &payer,
+        &facilitator,
+        &mut temp_env_file(&TempDir::new()),
+        &FundOptions::default(),
+        &JsonReporter,
+    )
+    .await
+    .unwrap_err();
+
+    assert!(err.to_string().contains("Transaction failed"), "{}", err);
+    assert_eq!(error::classify(&err), ("transaction_failed", 7));
+    assert!(rpc.sent_transactions().is_empty());
+}
+
+#[tokio::test]
+async fn test_requires_the_mint() {
+    let rpc = RecordingRpc::new(FakeRpc::new());
+    let (payer, facilitator, mint) =
+        (Keypair::new(), Keypair::new(), Keypair::new());
+
+    let err = test::run_with(
+        &rpc,
+        &Confirmer::default(),
+        &payer,
+        &facilitator,
+        &mint.pubkey(),
+        &JsonReporter,
+    )
+    .await
+    .unwrap_err();
+
+    assert!(err.to_string().contains("mint not found"), "{}", err);
+    assert_eq!(error::classify(&err), ("not_found", 8));
+    assert_eq!(rpc.count(RpcMethod::SendTransaction), 0);
+}
+
+#[tokio::test]
+async fn confirmer_resubmits_expired_transactions() {
+    let rpc = FakeRpc::new();
+    let payer = Keypair::new();
+    rpc.set_balance(&payer.pubkey(), LAMPORTS_PER_SOL);
+    rpc.drop_next_transactions(1);
+
+    let transfer = system_instruction::transfer(
+        &payer.pubkey(),
+        &Keypair::new().pubkey(),
+        1,
+    );
+    let blockhash = rpc.get_latest_blockhash().await.unwrap();
+    let mut tx = Transaction::new_signed_with_payer(
+        &[transfer],
+        Some(&payer.pubkey()),
+        &[&payer],
+        blockhash,
+    );
+
+    let (reporter, mut events) = ChannelReporter::new();
+    let sig = Confirmer::default()
+        .send_and_confirm(&rpc, &mut tx, &[&payer], "Transfer", &reporter)
+        .await
+        .unwrap();
+
+    let sent = rpc.sent_transactions();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].message.recent_blockhash, blockhash);
+    assert_ne!(sent[1].message.recent_blockhash, blockhash);
+    assert_eq!(sig, sent[1].signatures[0]);
+    assert_eq!(rpc.get_signature_status(&sig).await.unwrap(), Some(Ok(())));
+
+    drop(reporter);
+    let mut reported = Vec::new();
+    while let Some(event) = events.recv().await {
+        reported.push(event);
+    }
+    let expired = sent[0].signatures[0].to_string();
+    assert_eq!(reported.len(), 4);
+    assert_eq!(
+        reported[0],
+        Event::TransactionSent {
+            label: "Transfer".to_string(),
+            signature: expired.clone()
+        }
+    );
+    assert!(matches!(
+        &reported[1],
+        Event::Warning { message } if message.contains(&expired)
+    ));
+    assert_eq!(
+        reported[2],
+        Event::TransactionSent {
+            label: "Transfer".to_string(),
+            signature: sig.to_string()
+        }
+    );
+    assert_eq!(
+        reported[3],
+        Event::TransactionConfirmed {
+            label: "Transfer".to_string(),
+            signature: sig.to_string()
+        }
+    );
+}
+
+#[test]
+fn websocket_url_follows_the_rpc_url() {
+    assert_eq!(
+        confirm::websocket_url("https://api.devnet.solana.com").unwrap(),
+        "wss://api.devnet.solana.com/"
+    );
+    assert_eq!(
+        confirm::websocket_url("http://127.0.0.1:8899").unwrap(),
+        "ws://127.0.0.1:8900/"
+    );
+}
+
+#[test]
+fn confirmer_follows_the_cluster_profile() {
+    let mut cluster = ClusterProfile::builtin()
+        .into_iter()
+        .find(|profile| profile.name == "localnet")
+        .unwrap();
+    cluster.commitment = Commitment::Finalized;
+    let confirmer = Confirmer::for_cluster(&cluster).unwrap();
+    assert_eq!(confirmer.ws_url(), Some("ws://127.0.0.1:8900/"));
+    assert_eq!(confirmer.commitment(), Commitment::Finalized.config());
+
+    cluster.ws_url = Some("ws://127.0.0.1:9000/".to_string());
+    let confirmer = Confirmer::for_cluster(&cluster).unwrap();
+    assert_eq!(confirmer.ws_url(), Some("ws://127.0.0.1:9000/"));
+}
+
+#[test]
+fn project_resolves_files_from_its_root() {
+    let root = TempDir::new();
+    let nested = root.join("src").join("bin");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(root.join(".env"), "XFORTH_PROJECT_TEST=root\n").unwrap();
+
+    let project = Project::find(&nested).unwrap();
+    assert_eq!(project.root(), root.path());
+    assert_eq!(project.env_path(), root.join(".env"));
+    assert_eq!(project.manifest_path(), root.join("x402.toml"));
+    assert_eq!(
+        project.path(".xforth/ledger.jsonl"),
+        root.join(".xforth/ledger.jsonl")
+    );
+
+    let env_file = root.join("ci.env");
+    let project = Project::discover(None, Some(&env_file)).unwrap();
+    assert_eq!(project.root(), root.path());
+    assert_eq!(project.env_path(), env_file);
+    assert_eq!(
+        error::classify(&project.load_env().unwrap_err()),
+        ("env_missing", 3)
+    );
+
+    let project = Project::discover(Some(&nested), None).unwrap();
+    assert_eq!(project.env_path(), nested.join(".env"));
+}
+
+#[test]
+fn env_file_round_trips_values_through_dotenv() {
+    let dir = TempDir::new();
+    let values = [
+        ("PLAIN", "[1,2,3]"),
+        ("SPACES", "two words # not a comment"),
+        ("SINGLE", "it's"),
+        ("DOUBLE", "say \"hi\""),
+        ("DOLLAR", "$HOME and ${PATH}"),
+        ("BACKSLASH", "C:\\keys\\payer"),
+        ("BOTH", "it's \"$x\"\nnext\\n"),
+        ("EMPTY", ""),
+    ];
+    let mut env_file = temp_env_file(&dir);
+    for (key, value) in values {
+        env_file.set(key, value);
+    }
+    env_file.save().unwrap();
+
+    let reloaded = EnvFile::load(env_file.path()).unwrap();
+    let parsed: Vec<(String, String)> =
+        dotenvy::from_path_iter(dir.join(".env"))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+    for (key, value) in values {
+        assert_eq!(reloaded.get(key), Some(value), "{}", key);
+        let (_, dotenv) = parsed.iter().find(|(k, _)| k == key).unwrap();
+        assert_eq!(dotenv, value, "{}", key);
+    }
+}
+
+#[test]
+fn env_file_keeps_comments_and_order_on_upsert_and_remove() {
+    let dir = TempDir::new();
+    let path = dir.join(".env");
+    std::fs::write(
+        &path,
+        "# Keys\nexport A=1\n\nB='two words' # inline\nC=\"x\\ny\" # c\nA=3\n",
+    )
+    .unwrap();
+
+    let mut env_file = EnvFile::load(&path).unwrap();
+    assert_eq!(env_file.get("A"), Some("3"));
+    assert_eq!(env_file.get("B"), Some("two words"));
+    assert_eq!(env_file.get("C"), Some("x\ny"));
+
+    env_file.set("B", "2");
+    env_file.set("D", "4");
+    assert_eq!(env_file.remove("A"), Some("3".to_string()));
+    assert_eq!(env_file.remove("MISSING"), None);
+    env_file.save().unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "# Keys\n\nB=2\nC=\"x\\ny\" # c\nD=4\n"
+    );
+    let keys: Vec<_> = EnvFile::load(&path)
+        .unwrap()
+        .entries()
+        .map(|(key, _)| key.to_string())
+        .collect();
+    assert_eq!(keys, ["B", "C", "D"]);
+    assert!(!dir.join("..env.tmp").exists() && !dir.join(".env.tmp").exists());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn config_prefers_flags_then_project_then_user_config() {
+    let root = TempDir::new();
+    std::fs::write(root.join(".env"), "RPC_URL=http://127.0.0.1:8899\n")
+        .unwrap();
+    std::fs::write(
+        root.join("x402.toml"),
+        "[rpc]\nurl = \"https://ignored.example\"\nattempts = 7\n",
+    )
+    .unwrap();
+    let user_config = root.join("config.toml");
+    std::fs::write(
+        &user_config,
+        "[rpc]\nattempts = 9\nretry_delay_ms = 100\n",
+    )
+    .unwrap();
+    let project = Project::new(root.path());
+
+    let flags =
+        ConfigFlags { rpc_retry_jitter: Some(0.5), ..Default::default() };
+    let config =
+        Config::resolve_with(&flags, &project, Some(&user_config)).unwrap();
+    assert_eq!(config.rpc_url.value, "http://127.0.0.1:8899");
+    assert_eq!(config.rpc_url.source, Source::ProjectEnv(root.join(".env")));
+    assert_eq!(config.rpc_attempts.value, 7);
+    assert_eq!(
+        config.rpc_attempts.source,
+        Source::Manifest(root.join("x402.toml"))
+    );
+    assert_eq!(config.rpc_retry_delay_ms.value, 100);
+    assert_eq!(
+        config.rpc_retry_delay_ms.source,
+        Source::UserConfig(user_config.clone())
+    );
+    assert_eq!(
+        config.rpc_retry_jitter.source,
+        Source::Flag("--rpc-retry-jitter")
+    );
+    assert_eq!(config.ws_url.value, None);
+
+    let flags = ConfigFlags {
+        local: true,
+        rpc_url: Some("http://localhost:9000".to_string()),
+        ..Default::default()
+    };
+    let config = Config::resolve_with(&flags, &project, None).unwrap();
+    assert_eq!(config.rpc_url.value, "http://localhost:9000");
+    assert_eq!(config.rpc_retry_delay_ms.source, Source::Default);
+
+    std::fs::write(root.join("x402.toml"), "[rpc]\nattempts = 0\n").unwrap();
+    let err = Config::resolve_with(&ConfigFlags::default(), &project, None)
+        .unwrap_err();
+    assert_eq!(error::classify(&err), ("invalid_input", 2));
+}
+
+#[test]
+fn config_selects_cluster_profiles() {
+    let root = TempDir::new();
+    std::fs::write(root.join(".env"), "RPC_URL=http://127.0.0.1:8899/\n")
+        .unwrap();
+    let user_config = root.join("config.toml");
+    std::fs::write(
+        &user_config,
+        "[clusters.staging]\nrpc_url = \
+        \"https://rpc.staging.example\"\ncommitment = \"finalized\"\n\
+        network = \"solana-devnet\"\n",
+    )
+    .unwrap();
+    let project = Project::new(root.path());
+
+    // An RPC URL of a known cluster selects its profile
+    let config =
+        Config::resolve_with(&ConfigFlags::default(), &project, None).unwrap();
+    assert_eq!(config.cluster.value.name, "localnet");
+    assert!(config.cluster.value.faucet);
+    assert_eq!(config.cluster.value.network, "solana-localnet");
+
+    let flags = ConfigFlags {
+        cluster: Some("testnet".to_string()),
+        ..Default::default()
+    };
+    let config = Config::resolve_with(&flags, &project, None).unwrap();
+    assert_eq!(config.cluster.source, Source::Flag("--cluster"));
+    assert_eq!(config.rpc_url.value, "https://api.testnet.solana.com");
+    assert_eq!(config.rpc_url.source, Source::Cluster("testnet".to_string()));
+    assert_eq!(config.cluster.value.network, "solana-testnet");
+
+    let flags = ConfigFlags {
+        cluster: Some("staging".to_string()),
+        ..Default::default()
+    };
+    let config =
+        Config::resolve_with(&flags, &project, Some(&user_config)).unwrap();
+    assert_eq!(config.rpc_url.value, "https://rpc.staging.example");
+    assert_eq!(config.cluster.value.commitment, Commitment::Finalized);
+    assert!(!config.cluster.value.faucet);
+    assert_eq!(config.cluster.value.network, "solana-devnet");
+
+    // Any other RPC URL gets a custom profile that assumes nothing
+    let flags = ConfigFlags {
+        rpc_url: Some("http://10.0.0.5:8899".to_string()),
+        ..Default::default()
+    };
+    let config = Config::resolve_with(&flags, &project, None).unwrap();
+    assert_eq!(config.cluster.value.name, "custom");
+    assert!(!config.cluster.value.faucet);
+    assert_eq!(config.cluster.value.network, "unknown");
+
+    let flags = ConfigFlags {
+        cluster: Some("staging".to_string()),
+        ..Default::default()
+    };
+    let err = Config::resolve_with(&flags, &project, None).unwrap_err();
+    assert_eq!(error::classify(&err), ("invalid_input", 2));
+}
+
+#[tokio::test]
+async fn fund_refuses_airdrops_without_a_faucet() {
+    let rpc = RecordingRpc::new(FakeRpc::new());
+    let (payer, facilitator) = (Keypair::new(), Keypair::new());
+    let options = FundOptions { airdrop: false, ..Default::default() };
+
+    let err = fund::run_with(
+        &rpc,
+        &Confirmer::default(),
+        &payer,
+        &facilitator,
+        &mut temp_env_file(&TempDir::new()),
+        &options,
+        &JsonReporter,
+    )
+    .await
+    .unwrap_err();
+
+    assert_eq!(error::classify(&err), ("airdrop_unsupported", 6));
+    assert_eq!(rpc.count(RpcMethod::RequestAirdrop), 0);
+}
+
+#[tokio::test]
+async fn mainnet_guard_requires_opt_in_and_caps_spending() {
+    let rpc = FakeRpc::new();
+    let payer = Keypair::new();
+    rpc.set_balance(&payer.pubkey(), LAMPORTS_PER_SOL);
+    rpc.set_genesis_hash(guard::DEVNET_GENESIS_HASH.parse().unwrap());
+    let (network, confirmer) = MainnetGuard::default()
+        .check(&rpc, "test", Confirmer::default(), &JsonReporter)
+        .await
+        .unwrap();
+    assert_eq!(network, Network::Devnet);
+    assert!(confirmer.spend_guard().is_none());
+
+    rpc.set_genesis_hash(guard::MAINNET_GENESIS_HASH.parse().unwrap());
+    let err = MainnetGuard::default()
+        .check(&rpc, "test", Confirmer::default(), &JsonReporter)
+        .await
+        .unwrap_err();
+    assert_eq!(error::classify(&err), ("mainnet_not_allowed", 10));
+
+    // Room for one signature fee and a 1 lamport transfer, but not two
+    let dir = TempDir::new();
+    let spend_log = dir.join("spend.jsonl");
+    let mainnet_guard = MainnetGuard {
+        allow_mainnet: true,
+        assume_yes: true,
+        caps: SpendCaps { per_run: 7_000, per_day: 20_000 },
+        spend_log: spend_log.clone(),
+    };
+    let (network, confirmer) = mainnet_guard
+        .check(&rpc, "test", Confirmer::default(), &JsonReporter)
+        .await
+        .unwrap();
+    assert_eq!(network, Network::Mainnet);
+
+    let transfer = system_instruction::transfer(
+        &payer.pubkey(),
+        &Keypair::new().pubkey(),
+        1,
+    );
+    let blockhash = rpc.get_latest_blockhash().await.unwrap();
+    let mut tx = Transaction::new_signed_with_payer(
+        std::slice::from_ref(&transfer),
+        Some(&payer.pubkey()),
+        &[&payer],
+        blockhash,
+    );
+    confirmer
+        .send_and_confirm(&rpc, &mut tx, &[&payer], "Transfer", &JsonReporter)
+        .await
+        .unwrap();
+
+    let mut tx = Transaction::new_signed_with_payer(
+        &[transfer],
+        Some(&payer.pubkey()),
+        &[&payer],
+        blockhash,
+    );
+    let err = confirmer
+        .send_and_confirm(&rpc, &mut tx, &[&payer], "Transfer", &JsonReporter)
+        .await
+        .unwrap_err();
+    assert_eq!(error::classify(&err), ("spend_cap_exceeded", 10));
+    assert_eq!(rpc.sent_transactions().len(), 1);
+
+    // The daily cap counts what earlier runs recorded
+    let spend_guard =
+        SpendGuard::open(&spend_log, "test", SpendCaps::default()).unwrap();
+    assert_eq!(spend_guard.spent_today(), guard::LAMPORTS_PER_SIGNATURE + 1);
+}
+
+#[tokio::test]
+async fn spend_estimates_include_priority_fees() {
+    use solana_compute_budget_interface::ComputeBudgetInstruction;
+
+    let rpc = FakeRpc::new();
+    let payer = Keypair::new();
+    let transfer = system_instruction::transfer(
+        &payer.pubkey(),
+        &Keypair::new().pubkey(),
+        1,
+    );
+    let transaction = |instructions: &[_]| {
+        Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        )
+    };
+
+    let plain = transaction(std::slice::from_ref(&transfer));
+    assert_eq!(guard::fee(&plain), guard::LAMPORTS_PER_SIGNATURE);
+
+    // 10,000 units at 1,000,000 micro-lamports each
+    let prioritized = transaction(&[
+        ComputeBudgetInstruction::set_compute_unit_limit(10_000),
+        ComputeBudgetInstruction::set_compute_unit_price(1_000_000),
+        transfer.clone(),
+    ]);
+    assert_eq!(guard::priority_fee(&prioritized), 10_000);
+    assert_eq!(
+        guard::estimate_cost(&rpc, &prioritized).await.unwrap(),
+        guard::LAMPORTS_PER_SIGNATURE + 10_000 + 1
+    );
+
+    // Without a limit, each instruction gets 200,000 units, and fractions of
+    // a lamport round up
+    let unlimited = transaction(&[
+        ComputeBudgetInstruction::set_compute_unit_price(1),
+        transfer,
+    ]);
+    assert_eq!(guard::priority_fee(&unlimited), 1);
+}
+
+#[test]
+fn route_prices_convert_to_base_units_exactly() {
+    assert_eq!(token::parse_ui_amount("0.01", 6).unwrap(), 10_000);
+    assert_eq!(token::parse_ui_amount("1.1", 6).unwrap(), 1_100_000);
+    assert_eq!(token::parse_ui_amount("0.000001", 6).unwrap(), 1);
+    assert_eq!(token::parse_ui_amount("3", 0).unwrap(), 3);
+    assert_eq!(token::parse_ui_amount(".5", 1).unwrap(), 5);
+    assert_eq!(token::parse_ui_amount("2.500", 1).unwrap(), 25);
+    assert_eq!(
+        token::parse_ui_amount("18446744073709.551615", 6).unwrap(),
+        u64::MAX
+    );
+    for invalid in ["0.0000001", "1e-6", "-1", "", ".", "1.2.3", "abc"] {
+        let err = token::parse_ui_amount(invalid, 6).unwrap_err();
+        assert!(matches!(err, error::Error::InvalidInput(_)), "{}", invalid);
+    }
+    assert!(token::parse_ui_amount("18446744073709.551616", 6).is_err());
+
+    let manifest: Manifest = toml::from_str(
+        r#"
+        [payments]
+        default_amount = 0.1
+
+        [[routes]]
+        path = "/float"
+        price = 0.29
+
+        [[routes]]
+        path = "/string"
+        price = "0.000001"
+
+        [[routes]]
+        path = "/integer"
+        price = 2
+        "#,
+    )
+    .unwrap();
+    let default = manifest.payments.default_amount.unwrap();
+    assert_eq!(token::parse_ui_amount(default.as_str(), 6).unwrap(), 100_000);
+    let prices: Vec<u64> = manifest
+        .routes
+        .iter()
+        .map(|route| {
+            let price = route.price.as_ref().unwrap();
+            token::parse_ui_amount(price.as_str(), 6).unwrap()
+        })
+        .collect();
+    assert_eq!(prices, [290_000, 1, 2_000_000]);
+}
+
+/// Requirements for 1 xUSD on devnet
+fn payment_requirements() -> PaymentRequirements {
+    PaymentRequirements {
+        scheme: x402::SCHEME_EXACT.to_string(),
+        network: "solana-devnet".to_string(),
+        max_amount_required: "1000000".to_string(),
+        resource: "http://127.0.0.1:8080/weather".to_string(),
+        description: "Weather report".to_string(),
+        mime_type: "application/json".to_string(),
+        output_schema: None,
+        pay_to: Keypair::new().pubkey().to_string(),
+        max_timeout_seconds: 60,
+        asset: Keypair::new().pubkey().to_string(),
+        extra: Some(serde_json::json!({
+            "feePayer": Keypair::new().pubkey().to_string(),
+        })),
+    }
+}
+
+#[test]
+fn x402_headers_round_trip() {
+    let requirements = payment_requirements();
+    let payer = Keypair::new();
+    let transaction = Transaction::new_signed_with_payer(
+        &[x402::nonce_memo_ix(&x402::generate_nonce())],
+        Some(&payer.pubkey()),
+        &[&payer],
+        Hash::default(),
+    );
+
+    let payload = PaymentPayload::new(&requirements, &transaction).unwrap();
+    let decoded =
+        PaymentPayload::from_header(&payload.to_header().unwrap()).unwrap();
+    assert_eq!(decoded, payload);
+    assert_eq!(decoded.transaction().unwrap(), transaction);
+    decoded.matches(&requirements).unwrap();
+
+    let required = PaymentRequiredResponse::new(vec![requirements], None);
+    let decoded: PaymentRequiredResponse =
+        x402::decode_header(&x402::encode_header(&required).unwrap()).unwrap();
+    assert_eq!(decoded.select().unwrap(), &required.accepts[0]);
+
+    let settled = SettlementResponse {
+        success: true,
+        error_reason: None,
+        transaction: transaction.signatures[0].to_string(),
+        network: "solana-devnet".to_string(),
+        payer: Some(payer.pubkey().to_string()),
+    };
+    assert_eq!(
+        SettlementResponse::from_header(&settled.to_header().unwrap())
+            .unwrap(),
+        settled
+    );
+}
+
+#[test]
+fn x402_rejects_unsupported_and_malformed_messages() {
+    let requirements = payment_requirements();
+    let payer = Keypair::new();
+    let transaction = Transaction::new_signed_with_payer(
+        &[x402::nonce_memo_ix("nonce")],
+        Some(&payer.pubkey()),
+        &[&payer],
+        Hash::default(),
+    );
+    let payload = PaymentPayload::new(&requirements, &transaction).unwrap();
+    let header = |edit: fn(&mut PaymentPayload)| {
+        let mut payload = payload.clone();
+        edit(&mut payload);
+        x402::encode_header(&payload).unwrap()
+    };
+
+    assert!(matches!(
+        PaymentPayload::from_header(&header(|p| p.x402_version = 2)),
+        Err(X402Error::UnsupportedVersion(2))
+    ));
+    assert!(matches!(
+        PaymentPayload::from_header(&header(|p| p.scheme = "upto".into())),
+        Err(X402Error::UnsupportedScheme(_))
+    ));
+    assert!(matches!(
+        PaymentPayload::from_header(&header(|p| p.network = "base".into())),
+        Err(X402Error::UnsupportedNetwork(_))
+    ));
+    assert!(matches!(
+        PaymentPayload::from_header(&header(|p| {
+            p.payload.transaction = "not a transaction".into()
+        })),
+        Err(X402Error::Base64(_))
+    ));
+    assert!(matches!(
+        PaymentPayload::from_header("%%% not base64 %%%"),
+        Err(X402Error::Base64(_))
+    ));
+    assert!(matches!(
+        PaymentPayload::from_header(&BASE64.encode("{\"x402Version\": 1}")),
+        Err(X402Error::Json(_))
+    ));
+
+    let mut mainnet = requirements.clone();
+    mainnet.network = "solana".to_string();
+    assert!(payload.matches(&mainnet).is_err());
+    let mut free = requirements;
+    free.max_amount_required = "0".to_string();
+    assert!(matches!(
+        PaymentRequiredResponse::new(vec![free], None).select(),
+        Err(X402Error::InvalidField { field: "maxAmountRequired", .. })
+    ));
+}
+
+#[test]
+fn keys_encrypt_and_decrypt_the_project_keypairs() {
+    let root = TempDir::new();
+    let (payer, facilitator) = (Keypair::new(), Keypair::new());
+    std::fs::write(
+        root.join(".env"),
+        format!(
+            "PAYER_KEYPAIR={}\nFACILITATOR_KEYPAIR={}\n",
+            keypair_to_json(&payer),
+            keypair_to_json(&facilitator)
+        ),
+    )
+    .unwrap();
+    let project = Project::new(root.path());
+
+    let report =
+        keys::encrypt(&project, "correct horse", &JsonReporter).unwrap();
+    assert_eq!(report.changed, ["PAYER_KEYPAIR", "FACILITATOR_KEYPAIR"]);
+    let env_file = project.env_file().unwrap();
+    let sealed = env_file.get("PAYER_KEYPAIR").unwrap();
+    assert!(keystore::is_encrypted(sealed));
+    assert_eq!(keystore::public_key(sealed).unwrap(), payer.pubkey());
+    assert_eq!(
+        keystore::decrypt(sealed, "correct horse").unwrap().pubkey(),
+        payer.pubkey()
+    );
+    let err = keystore::decrypt(sealed, "wrong horse").unwrap_err();
+    assert_eq!(error::classify(&err), ("passphrase_invalid", 3));
+
+    // Already encrypted keypairs are left alone
+    let report =
+        keys::encrypt(&project, "correct horse", &JsonReporter).unwrap();
+    assert!(report.changed.is_empty());
+
+    let report =
+        keys::decrypt(&project, "correct horse", &JsonReporter).unwrap();
+    assert_eq!(report.changed, ["PAYER_KEYPAIR", "FACILITATOR_KEYPAIR"]);
+    let env_file = project.env_file().unwrap();
+    assert_eq!(
+        env_file.get("FACILITATOR_KEYPAIR").unwrap(),
+        keypair_to_json(&facilitator)
+    );
+}
+
+#[test]
+fn keystore_refuses_unbounded_costs_and_empty_passphrases() {
+    let payer = Keypair::new();
+    let sealed = keystore::encrypt(&payer, "correct horse").unwrap();
+    let cost = format!(":{}:", keystore::SCRYPT_LOG_N);
+    for log_n in [1, keystore::MAX_SCRYPT_LOG_N + 1, 63] {
+        let tampered = sealed.replacen(&cost, &format!(":{}:", log_n), 1);
+        let err = keystore::decrypt(&tampered, "correct horse").unwrap_err();
+        assert_eq!(error::classify(&err), ("invalid_input", 2));
+        assert!(keystore::public_key(&tampered).is_err());
+    }
+
+    // No other test reads the passphrase variable
+    std::env::set_var(keystore::PASSPHRASE_VAR, "");
+    let err = keystore::new_passphrase().unwrap_err();
+    assert_eq!(error::classify(&err), ("passphrase_invalid", 3));
+    std::env::set_var(keystore::PASSPHRASE_VAR, "correct horse");
+    assert_eq!(keystore::new_passphrase().unwrap(), "correct horse");
+    std::env::remove_var(keystore::PASSPHRASE_VAR);
+
+    // Front ends without a terminal set the passphrase and never prompt
+    keystore::set_passphrase("gui horse");
+    assert_eq!(keystore::passphrase().unwrap(), "gui horse");
+    keystore::set_passphrase("");
+    keystore::disable_prompt();
+    let err = keystore::passphrase().unwrap_err();
+    assert_eq!(error::classify(&err), ("passphrase_missing", 3));
+}
+
+#[test]
+fn mnemonic_derives_slip10_and_solana_wallet_keys() {
+    // SLIP-0010 ed25519 test vector 1
+    let seed = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a,
+        0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+    ];
+    let keypair =
+        mnemonic::derive_from_seed(&seed, &"m/0H/1H".parse().unwrap());
+    assert_eq!(
+        keypair.to_bytes()[..32],
+        [
+            0xb1, 0xd0, 0xba, 0xd4, 0x04, 0xbf, 0x35, 0xda, 0x78, 0x5a, 0x64,
+            0xca, 0x1a, 0xc5, 0x4b, 0x26, 0x17, 0x21, 0x1d, 0x27, 0x77, 0x69,
+            0x6f, 0xbf, 0xfa, 0xf2, 0x08, 0xf7, 0x46, 0xae, 0x84, 0xf2,
+        ]
+    );
+
+    // The address Phantom and Solflare show for the first account
+    let phrase = "abandon abandon abandon abandon abandon abandon abandon \
+        abandon abandon abandon abandon about";
+    let payer = mnemonic::derive(phrase, &DerivationPath::solana(0)).unwrap();
+    assert_eq!(
+        payer.pubkey().to_string(),
+        "HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk"
+    );
+    assert_eq!(DerivationPath::solana(0).to_string(), mnemonic::DEFAULT_PATH);
+
+    assert_eq!(
+        error::classify(
+            &mnemonic::derive("abandon about", &DerivationPath::solana(0))
+                .unwrap_err()
+        ),
+        ("invalid_input", 2)
+    );
+    assert!("44'/501'".parse::<DerivationPath>().is_err());
+
+    let generated = mnemonic::generate();
+    assert_eq!(generated.split_whitespace().count(), 12);
+
+    // Recovery writes the same keypairs back into .env
+    let root = TempDir::new();
+    let project = Project::new(root.path());
+    let report = keys::recover(&project, phrase, None, &JsonReporter).unwrap();
+    assert_eq!(report.payer_pubkey, payer.pubkey().to_string());
+    let env_file = project.env_file().unwrap();
+    assert_eq!(
+        keystore::pubkey_of(env_file.get("PAYER_KEYPAIR").unwrap()).unwrap(),
+        payer.pubkey()
+    );
+    assert_eq!(
+        keystore::pubkey_of(env_file.get("FACILITATOR_KEYPAIR").unwrap())
+            .unwrap()
+            .to_string(),
+        report.facilitator_pubkey
+    );
+}
+
+#[cfg(feature = "offline")]
+mod offline {
+    use super::*;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use solana_compute_budget_interface::ComputeBudgetInstruction;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::pubkey::Pubkey;
+    use spl_token_2022_interface::extension::{
+        metadata_pointer::MetadataPointer, transfer_fee::TransferFeeConfig,
+        BaseStateWithExtensions, StateWithExtensions,
+    };
+    use spl_token_2022_interface::state::Mint;
+    use spl_token_metadata_interface::state::TokenMetadata;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use xforth::facilitator::{
+        Facilitator, MAX_COMPUTE_UNIT_LIMIT, MAX_COMPUTE_UNIT_PRICE,
+    };
+    use xforth::ledger::Ledger;
+    use xforth::offline::{run_flow, SvmRpc};
+    use xforth::pay;
+    use xforth::token::{self, MetadataFields, MintInfo, TokenProgram};
+    use xforth::x402::FacilitatorRequest;
+
+    /// A funded Payer holding xUSD, a facilitator settling payments on the
+    /// VM with its ledger in a temp directory, and requirements for 1 xUSD
+    /// to a fresh recipient
+    struct Payment {
+        payer: Keypair,
+        facilitator_keypair: Keypair,
+        facilitator: Facilitator<SvmRpc>,
+        mint: MintInfo,
+        requirements: PaymentRequirements,
+        dir: TempDir,
+    }
+
+    impl Payment {
+        async fn new() -> Self {
+            let rpc = SvmRpc::new();
+            let (payer, facilitator) = (Keypair::new(), Keypair::new());
+            let dir = TempDir::new();
+            let mut env_file = temp_env_file(&dir);
+            fund::run_with(
+                &rpc,
+                &Confirmer::default(),
+                &payer,
+                &facilitator,
+                &mut env_file,
+                &FundOptions::default(),
+                &JsonReporter,
+            )
+            .await
+            .unwrap();
+            let address =
+                Pubkey::from_str(env_file.get("XUSD_MINT").unwrap()).unwrap();
+            let account = rpc.get_account(&address).await.unwrap().unwrap();
+            let mint = MintInfo::from_account(&address, &account).unwrap();
+
+            let requirements = PaymentRequirements {
+                network: "solana-localnet".to_string(),
+                asset: address.to_string(),
+                extra: Some(serde_json::json!({
+                    "feePayer": facilitator.pubkey().to_string(),
+                })),
+                ..payment_requirements()
+            };
+            let facilitator_keypair = facilitator.insecure_clone();
+            let facilitator = Facilitator::with_client(
+                rpc,
+                "solana-localnet",
+                Confirmer::default(),
+                facilitator,
+                Ledger::open(dir.join("ledger.jsonl")).unwrap(),
+            );
+            Self {
+                payer,
+                facilitator_keypair,
+                facilitator,
+                mint,
+                requirements,
+                dir,
+            }
+        }
+
+        fn rpc(&self) -> &SvmRpc {
+            self.facilitator.client()
+        }
+
+        async fn transaction(&self) -> Transaction {
+            pay::build_payment(
+                self.rpc(),
+                &self.payer,
+                &self.mint,
+                &self.requirements.pay_to().unwrap(),
+                self.requirements.amount().unwrap(),
+                Some(&self.facilitator.pubkey()),
+            )
+            .await
+            .unwrap()
+        }
+
+        /// Payment transaction carrying `nonce`, with `budget` instructions
+        /// first and its recipient token account funded by `funder`
+        async fn transaction_with(
+            &self,
+            budget: &[Instruction],
+            funder: &Pubkey,
+            nonce: &str,
+        ) -> Transaction {
+            let pay_to = self.requirements.pay_to().unwrap();
+            let (mint, program) = (&self.mint.address, self.mint.program);
+            let mut instructions = budget.to_vec();
+            instructions.extend([
+                x402::nonce_memo_ix(nonce),
+                token::create_associated_token_account_ix(
+                    funder, &pay_to, mint, program,
+                ),
+                token::transfer_checked_ix(
+                    program,
+                    &token::associated_token_address(
+                        &self.payer.pubkey(),
+                        mint,
+                        program,
+                    ),
+                    mint,
+                    &token::associated_token_address(&pay_to, mint, program),
+                    &self.payer.pubkey(),
+                    self.requirements.amount().unwrap(),
+                    self.mint.decimals,
+                )
+                .unwrap(),
+            ]);
+            let mut transaction = Transaction::new_with_payer(
+                &instructions,
+                Some(&self.facilitator.pubkey()),
+            );
+            let blockhash = self.rpc().get_latest_blockhash().await.unwrap();
+            transaction.partial_sign(&[&self.payer], blockhash);
+            transaction
+        }
+
+        fn request(&self, transaction: &Transaction) -> FacilitatorRequest {
+            FacilitatorRequest::new(
+                PaymentPayload::new(&self.requirements, transaction).unwrap(),
+                self.requirements.clone(),
+            )
+        }
+
+        /// Rejection code of `request`
+        async fn rejection(&self, request: &FacilitatorRequest) -> &str {
+            self.facilitator.verify(request).await.err().unwrap().code()
+        }
+
+        /// Recipient's token balance
+        async fn received(&self) -> u64 {
+            let destination = token::associated_token_address(
+                &self.requirements.pay_to().unwrap(),
+                &self.mint.address,
+                self.mint.program,
+            );
+            token::token_balance(self.rpc(), &destination).await.unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn facilitator_verifies_and_settles_a_sponsored_payment() {
+        let payment = Payment::new().await;
+        let request = payment.request(&payment.transaction().await);
+
+        let verified = payment.facilitator.verify(&request).await.unwrap();
+        assert_eq!(verified.payer, payment.payer.pubkey());
+        assert_eq!(verified.amount, 1_000_000);
+        assert!(verified.nonce.is_some());
+        assert_eq!(payment.received().await, 0);
+
+        let settled =
+            payment.facilitator.settle(&request, &JsonReporter).await;
+        assert!(settled.success, "{:?}", settled.error_reason);
+        assert_eq!(settled.transaction, verified.signature.to_string());
+        assert_eq!(payment.received().await, 1_000_000);
+        let ledger = Ledger::open(payment.dir.join("ledger.jsonl")).unwrap();
+        assert_eq!(ledger.entries().len(), 1);
+        assert_eq!(ledger.entries()[0].signature, settled.transaction);
+    }
+
+    #[tokio::test]
+    async fn facilitator_rejects_mismatched_and_malformed_payments() {
+        let payment = Payment::new().await;
+        let transaction = payment.transaction().await;
+
+        let mut request = payment.request(&transaction);
+        request.payment_requirements.max_amount_required =
+            "2000000".to_string();
+        assert_eq!(payment.rejection(&request).await, "amount_mismatch");
+
+        let mut request = payment.request(&transaction);
+        request.payment_requirements.pay_to =
+            Keypair::new().pubkey().to_string();
+        assert_eq!(payment.rejection(&request).await, "invalid_instruction");
+
+        // Account indices past the end of the account keys
+        let mut out_of_range = transaction.clone();
+        out_of_range.message.instructions[0].program_id_index = 99;
+        let request = payment.request(&out_of_range);
+        assert_eq!(payment.rejection(&request).await, "invalid_payload");
+        let mut out_of_range = transaction.clone();
+        out_of_range.message.instructions[1].accounts[2] = 99;
+        let request = payment.request(&out_of_range);
+        assert_eq!(payment.rejection(&request).await, "invalid_payload");
+
+        let mut unsigned = transaction.clone();
+        unsigned.signatures[1] = Default::default();
+        let request = payment.request(&unsigned);
+        assert_eq!(payment.rejection(&request).await, "invalid_signature");
+        assert!(
+            !payment.facilitator.settle(&request, &JsonReporter).await.success
+        );
+        assert_eq!(payment.received().await, 0);
+    }
+
+    #[tokio::test]
+    async fn facilitator_releases_reservations_of_failed_settlements() {
+        let payment = Payment::new().await;
+        let sponsor = payment.facilitator.pubkey();
+
+        // Reserved signatures and nonces count as settled until released
+        let mut ledger = Ledger::open(payment.dir.join("held.jsonl")).unwrap();
+        ledger.reserve("signature", Some("N2"));
+        assert!(ledger.contains_signature("signature"));
+        assert!(ledger.contains_nonce("N2"));
+        ledger.release("signature", Some("N2"));
+        assert!(!ledger.contains_signature("signature"));
+        assert!(!ledger.contains_nonce("N2"));
+
+        // A settlement that fails on chain gives its nonce back
+        let mut expired = payment.transaction_with(&[], &sponsor, "N2").await;
+        expired.partial_sign(&[&payment.payer], Hash::new_unique());
+        let response = payment
+            .facilitator
+            .settle(&payment.request(&expired), &JsonReporter)
+            .await;
+        assert!(!response.success);
+        assert!(response
+            .error_reason
+            .unwrap()
+            .starts_with("blockhash_expired"));
+
+        let retried = payment.transaction_with(&[], &sponsor, "N2").await;
+        let response = payment
+            .facilitator
+            .settle(&payment.request(&retried), &JsonReporter)
+            .await;
+        assert!(response.success);
+        assert_eq!(payment.received().await, 1_000_000);
+    }
+
+    #[tokio::test]
+    async fn facilitator_rejects_replays_across_restarts() {
+        let payment = Payment::new().await;
+        let sponsor = payment.facilitator.pubkey();
+        let settled_tx = payment.transaction_with(&[], &sponsor, "N1").await;
+        let settled = payment.request(&settled_tx);
+        let signature = payment
+            .facilitator
+            .settle(&settled, &JsonReporter)
+            .await
+            .transaction;
+
+        // The same payload, and a new transaction reusing its nonce
+        let same_nonce = payment.request(
+            &payment
+                .transaction_with(
+                    &[ComputeBudgetInstruction::set_compute_unit_limit(
+                        MAX_COMPUTE_UNIT_LIMIT,
+                    )],
+                    &sponsor,
+                    "N1",
+                )
+                .await,
+        );
+        assert_ne!(same_nonce.payment_payload, settled.payment_payload);
+        for replay in [&settled, &same_nonce] {
+            assert_eq!(payment.rejection(replay).await, "duplicate_payment");
+            let response =
+                payment.facilitator.settle(replay, &JsonReporter).await;
+            assert!(!response.success);
+            assert!(response
+                .error_reason
+                .unwrap()
+                .starts_with("duplicate_payment"));
+        }
+        assert_eq!(payment.received().await, 1_000_000);
+
+        // A restarted facilitator reloads both from the ledger file, before
+        // it would even look at the chain
+        let ledger = Ledger::open(payment.dir.join("ledger.jsonl")).unwrap();
+        assert!(ledger.contains_signature(&signature));
+        assert!(ledger.contains_nonce("N1"));
+        let restarted = Facilitator::with_client(
+            SvmRpc::new(),
+            "solana-localnet",
+            Confirmer::default(),
+            payment.facilitator_keypair.insecure_clone(),
+            ledger,
+        );
+        for replay in [&settled, &same_nonce] {
+            let err = restarted.verify(replay).await.err().unwrap();
+            assert_eq!(err.code(), "duplicate_payment");
+        }
+    }
+
+    #[tokio::test]
+    async fn facilitator_funds_the_recipient_account_it_sponsors() {
+        let payment = Payment::new().await;
+        let payer = payment.payer.pubkey();
+        let lamports = payment.rpc().get_balance(&payer).await.unwrap();
+
+        let request = payment.request(&payment.transaction().await);
+        let settled =
+            payment.facilitator.settle(&request, &JsonReporter).await;
+        assert!(settled.success, "{:?}", settled.error_reason);
+        assert_eq!(payment.received().await, 1_000_000);
+        assert_eq!(payment.rpc().get_balance(&payer).await.unwrap(), lamports);
+
+        // The client may not fund it instead
+        let nonce = x402::generate_nonce();
+        let transaction = payment.transaction_with(&[], &payer, &nonce).await;
+        let request = payment.request(&transaction);
+        assert_eq!(payment.rejection(&request).await, "invalid_instruction");
+    }
+
+    #[tokio::test]
+    async fn facilitator_accepts_only_capped_compute_budgets() {
+        let payment = Payment::new().await;
+        let (payment, sponsor) = (&payment, payment.facilitator.pubkey());
+        let rejection = |budget: Vec<Instruction>| async move {
+            let transaction =
+                payment.transaction_with(&budget, &sponsor, "nonce").await;
+            payment
+                .facilitator
+                .verify(&payment.request(&transaction))
+                .await
+                .err()
+        };
+
+        let capped = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(
+                MAX_COMPUTE_UNIT_LIMIT,
+            ),
+            ComputeBudgetInstruction::set_compute_unit_price(
+                MAX_COMPUTE_UNIT_PRICE,
+            ),
+        ];
+        assert!(rejection(capped).await.is_none());
+
+        for budget in [
+            vec![
+                ComputeBudgetInstruction::set_compute_unit_limit(
+                    MAX_COMPUTE_UNIT_LIMIT,
+                ),
+                ComputeBudgetInstruction::set_compute_unit_price(
+                    MAX_COMPUTE_UNIT_PRICE + 1,
+                ),
+            ],
+            // The runtime default limit would exceed the cap
+            vec![ComputeBudgetInstruction::set_compute_unit_price(
+                MAX_COMPUTE_UNIT_PRICE,
+            )],
+            vec![ComputeBudgetInstruction::set_compute_unit_limit(
+                MAX_COMPUTE_UNIT_LIMIT + 1,
+            )],
+            vec![
+                ComputeBudgetInstruction::set_compute_unit_price(1),
+                ComputeBudgetInstruction::set_compute_unit_price(1),
+            ],
+            vec![ComputeBudgetInstruction::request_heap_frame(64 * 1024)],
+        ] {
+            let err = rejection(budget).await.unwrap();
+            assert_eq!(err.code(), "invalid_compute_budget");
+        }
+    }
+
+    /// Run `fund` on `rpc` and read back the mint it used
+    async fn fund_on(
+        rpc: &SvmRpc,
+        payer: &Keypair,
+        facilitator: &Keypair,
+        env_file: &mut EnvFile,
+        options: &FundOptions,
+    ) -> (fund::FundReport, MintInfo) {
+        let report = fund::run_with(
+            rpc,
+            &Confirmer::default(),
+            payer,
+            facilitator,
+            env_file,
+            options,
+            &JsonReporter,
+        )
+        .await
+        .unwrap();
+        let address = Pubkey::from_str(&report.mint_pubkey).unwrap();
+        let account = rpc.get_account(&address).await.unwrap().unwrap();
+        (report, MintInfo::from_account(&address, &account).unwrap())
+    }
+
+    #[tokio::test]
+    async fn fund_mints_xusd_into_the_payer_token_account() {
+        let rpc = SvmRpc::new();
+        let (payer, facilitator) = (Keypair::new(), Keypair::new());
+        let dir = TempDir::new();
+        let mut env_file = temp_env_file(&dir);
+
+        let (funded, mint) = fund_on(
+            &rpc,
+            &payer,
+            &facilitator,
+            &mut env_file,
+            &FundOptions::default(),
+        )
+        .await;
+        assert_eq!(mint.mint_authority, Some(payer.pubkey()));
+        assert_eq!(mint.decimals, fund::XUSD_DECIMALS);
+        let payer_account = token::associated_token_address(
+            &payer.pubkey(),
+            &mint.address,
+            mint.program,
+        );
+        assert_eq!(funded.payer_token_account, payer_account.to_string());
+        assert_eq!(funded.xusd_minted, 1000.0);
+        assert!(funded.xusd_minted_tx.is_some());
+        assert_eq!(
+            token::token_balance(&rpc, &payer_account).await.unwrap(),
+            1000 * 10_u64.pow(fund::XUSD_DECIMALS as u32)
+        );
+
+        // Only the difference is minted when the target is raised
+        let options = FundOptions { xusd_amount: 1500, ..Default::default() };
+        let (topped_up, _) =
+            fund_on(&rpc, &payer, &facilitator, &mut env_file, &options).await;
+        assert_eq!(topped_up.xusd_minted, 500.0);
+        assert_eq!(topped_up.payer_xusd_balance, 1500.0);
+        assert_eq!(
+            token::token_balance(&rpc, &payer_account).await.unwrap(),
+            1500 * 10_u64.pow(fund::XUSD_DECIMALS as u32)
+        );
+
+        // Another Payer cannot mint with it
+        let err = fund::run_with(
+            &rpc,
+            &Confirmer::default(),
+            &Keypair::new(),
+            &facilitator,
+            &mut env_file,
+            &options,
+            &JsonReporter,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error::classify(&err), ("invalid_input", 2));
+    }
+
+    #[tokio::test]
+    async fn fund_creates_token_2022_mints_with_fee_and_metadata() {
+        let rpc = SvmRpc::new();
+        let (payer, facilitator) = (Keypair::new(), Keypair::new());
+        let dir = TempDir::new();
+        let options = FundOptions {
+            token_program: TokenProgram::Token2022,
+            fee_basis_points: 25,
+            max_fee: 2,
+            metadata: MetadataFields {
+                name: "Staging USD".to_string(),
+                symbol: "sUSD".to_string(),
+                uri: "https://example.com/susd.json".to_string(),
+            },
+            ..Default::default()
+        };
+        let (funded, mint) = fund_on(
+            &rpc,
+            &payer,
+            &facilitator,
+            &mut temp_env_file(&dir),
+            &options,
+        )
+        .await;
+        assert_eq!(funded.token_program, "token-2022");
+        assert_eq!(mint.program, TokenProgram::Token2022);
+        let fee = mint.transfer_fee.unwrap();
+        assert_eq!((fee.basis_points, fee.maximum_fee), (25, 2_000_000));
+
+        let account = rpc.get_account(&mint.address).await.unwrap().unwrap();
+        let state =
+            StateWithExtensions::<Mint>::unpack(&account.data).unwrap();
+        let fee_config = state.get_extension::<TransferFeeConfig>().unwrap();
+        assert_eq!(
+            Option::<Pubkey>::from(fee_config.withdraw_withheld_authority),
+            Some(facilitator.pubkey())
+        );
+        let pointer = state.get_extension::<MetadataPointer>().unwrap();
+        assert_eq!(
+            Option::<Pubkey>::from(pointer.metadata_address),
+            Some(mint.address)
+        );
+        let metadata =
+            state.get_variable_len_extension::<TokenMetadata>().unwrap();
+        assert_eq!(metadata.name, "Staging USD");
+        assert_eq!(metadata.symbol, "sUSD");
+        assert_eq!(metadata.uri, "https://example.com/susd.json");
+        assert_eq!(funded.payer_xusd_balance, 1000.0);
+    }
+
+    /// Resource server answering 402 with `requirements`, which lands the
+    /// payment itself instead of going through a facilitator
+    async fn serve_paywall(
+        rpc: Arc<SvmRpc>,
+        requirements: PaymentRequirements,
+    ) -> String {
+        let handler = move |headers: HeaderMap| async move {
+            let Some(header) = headers.get(x402::PAYMENT_HEADER) else {
+                let body = PaymentRequiredResponse::new(
+                    vec![requirements.clone()],
+                    None,
+                );
+                return (StatusCode::PAYMENT_REQUIRED, Json(body))
+                    .into_response();
+            };
+            let payload =
+                PaymentPayload::from_header(header.to_str().unwrap()).unwrap();
+            payload.matches(&requirements).unwrap();
+            let transaction = payload.transaction().unwrap();
+            let signature = rpc.send_transaction(&transaction).await.unwrap();
+            let receipt = SettlementResponse {
+                success: true,
+                error_reason: None,
+                transaction: signature.to_string(),
+                network: requirements.network.clone(),
+                payer: None,
+            };
+            (
+                [(
+                    x402::PAYMENT_RESPONSE_HEADER,
+                    receipt.to_header().unwrap(),
+                )],
+                "forecast: sunny",
+            )
+                .into_response()
+        };
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/weather", listener.local_addr().unwrap());
+        let app = Router::new().route("/weather", get(handler));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn pay_answers_402_with_a_signed_payment() {
+        let rpc = Arc::new(SvmRpc::new());
+        let (payer, facilitator) = (Keypair::new(), Keypair::new());
+        let dir = TempDir::new();
+        let (_, mint) = fund_on(
+            &rpc,
+            &payer,
+            &facilitator,
+            &mut temp_env_file(&dir),
+            &FundOptions::default(),
+        )
+        .await;
+        let requirements = PaymentRequirements {
+            network: "solana-localnet".to_string(),
+            asset: mint.address.to_string(),
+            extra: None,
+            ..payment_requirements()
+        };
+        let pay_to = requirements.pay_to().unwrap();
+        let url = serve_paywall(rpc.clone(), requirements).await;
+        let network = "solana-localnet";
+
+        // On another network, nothing is signed or sent
+        let err = pay::run_with(
+            &*rpc,
+            &payer,
+            &url,
+            "solana-devnet",
+            None,
+            &JsonReporter,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error::classify(&err), ("invalid_input", 2));
+
+        // Over the cap, nothing is signed or sent
+        let err = pay::run_with(
+            &*rpc,
+            &payer,
+            &url,
+            network,
+            Some(999_999),
+            &JsonReporter,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error::classify(&err), ("payment_failed", 9));
+
+        let paid =
+            pay::run_with(&*rpc, &payer, &url, network, None, &JsonReporter)
+                .await
+                .unwrap();
+        assert!(paid.paid);
+        assert_eq!(paid.status, 200);
+        assert_eq!(paid.body, "forecast: sunny");
+        assert_eq!(paid.amount, Some(1_000_000));
+        let settlement = paid.settlement.unwrap();
+        assert!(settlement.success);
+        assert_eq!(
+            paid.transaction_signature,
+            Some(settlement.transaction.clone())
+        );
+        let received = token::associated_token_address(
+            &pay_to,
+            &mint.address,
+            mint.program,
+        );
+        assert_eq!(
+            token::token_balance(&*rpc, &received).await.unwrap(),
+            1_000_000
+        );
+    }
+
+    #[tokio::test]
+    async fn spl_token_payment() {
+        let report =
+            run_flow(&FundOptions::default(), &JsonReporter).await.unwrap();
+        assert_eq!(report.token_program, "spl-token");
+        assert_eq!(report.payer_tokens_before, 1_000_000_000);
+        assert_eq!(
+            report.payer_tokens_after,
+            report.payer_tokens_before - report.amount
+        );
+        assert_eq!(report.transfer_fee, 0);
+        assert_eq!(report.facilitator_tokens_after, report.amount);
+    }
+
+    #[tokio::test]
+    async fn token_2022_payment_withholds_transfer_fee() {
+        let options = FundOptions {
+            token_program: TokenProgram::Token2022,
+            ..Default::default()
+        };
+        let report = run_flow(&options, &JsonReporter).await.unwrap();
+        assert_eq!(report.token_program, "token-2022");
+        // 50 bps of 1 xUSD
+        assert_eq!(report.transfer_fee, 5_000);
+        assert_eq!(
+            report.facilitator_tokens_after,
+            report.amount - report.transfer_fee
+        );
+    }
+
+    #[tokio::test]
+    async fn fund_retries_transient_errors_then_reuses_the_mint() {
+        let retrying =
+            RetryingRpc::new(RecordingRpc::new(SvmRpc::new()), fast_retries());
+        let rpc = retrying.inner();
+        rpc.fail_next(RpcMethod::RequestAirdrop, connection_reset());
+        rpc.fail_next(RpcMethod::GetLatestBlockhash, connection_reset());
+
+        let (payer, facilitator) = (Keypair::new(), Keypair::new());
+        let dir = TempDir::new();
+        let mut env_file = temp_env_file(&dir);
+        let options = FundOptions::default();
+
+        let funded = fund::run_with(
+            &retrying,
+            &Confirmer::default(),
+            &payer,
+            &facilitator,
+            &mut env_file,
+            &options,
+            &JsonReporter,
+        )
+        .await
+        .unwrap();
+        assert_eq!(rpc.count(RpcMethod::RequestAirdrop), 3);
+        assert!(funded.mint_created);
+        assert!(
+            funded.payer_airdrop_tx.is_some()
+                && funded.facilitator_airdrop_tx.is_some()
+        );
+        assert_eq!(funded.payer_xusd_balance, 1000.0);
+        let mint =
+            Pubkey::from_str(env_file.get("XUSD_MINT").unwrap()).unwrap();
+        assert_eq!(funded.mint_pubkey, mint.to_string());
+        let saved = EnvFile::load(env_file.path()).unwrap();
+        assert_eq!(saved.get("XUSD_MINT"), Some(mint.to_string().as_str()));
+
+        // Already funded: no airdrops, no new mint, nothing sent
+        let sent = rpc.count(RpcMethod::SendTransaction);
+        let refunded = fund::run_with(
+            &retrying,
+            &Confirmer::default(),
+            &payer,
+            &facilitator,
+            &mut env_file,
+            &options,
+            &JsonReporter,
+        )
+        .await
+        .unwrap();
+        assert_eq!(rpc.count(RpcMethod::RequestAirdrop), 3);
+        assert_eq!(rpc.count(RpcMethod::SendTransaction), sent);
+        assert!(!refunded.mint_created);
+        assert_eq!(refunded.payer_airdrop_tx, None);
+        assert_eq!(refunded.xusd_minted, 0.0);
+        assert_eq!(env_file.get("XUSD_MINT"), Some(mint.to_string().as_str()));
+
+        let tested = test::run_with(
+            &retrying,
+            &Confirmer::default(),
+            &payer,
+            &facilitator,
+            &mint,
+            &JsonReporter,
+        )
+        .await
+        .unwrap();
+        assert_eq!(rpc.count(RpcMethod::SendTransaction), sent + 1);
+        assert_eq!(tested.mint, mint.to_string());
+        assert_eq!(tested.payer_xusd_after, tested.payer_xusd_before - 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_pays_the_facilitator_in_xusd() {
+        let rpc = SvmRpc::new();
+        let (payer, facilitator) = (Keypair::new(), Keypair::new());
+        let dir = TempDir::new();
+        let options = FundOptions {
+            token_program: TokenProgram::Token2022,
+            ..Default::default()
+        };
+        let (_, mint) = fund_on(
+            &rpc,
+            &payer,
+            &facilitator,
+            &mut temp_env_file(&dir),
+            &options,
+        )
+        .await;
+        let sol = rpc.get_balance(&payer.pubkey()).await.unwrap();
+
+        let tested = test::run_with(
+            &rpc,
+            &Confirmer::default(),
+            &payer,
+            &facilitator,
+            &mint.address,
+            &JsonReporter,
+        )
+        .await
+        .unwrap();
+        assert_eq!(tested.token_program, "token-2022");
+        assert_eq!(tested.fee_payer, facilitator.pubkey().to_string());
+        assert!(!tested.created_token_account);
+        assert_eq!(tested.transfer_amount_xusd, 1);
+        assert_eq!(tested.transfer_fee, 0.005);
+        assert_eq!(tested.payer_xusd_after, 999.0);
+        assert_eq!(
+            tested.facilitator_xusd_after,
+            tested.facilitator_xusd_before + 0.995
+        );
+        // The Facilitator sponsors the fee, so the Payer spends no SOL
+        assert_eq!(rpc.get_balance(&payer.pubkey()).await.unwrap(), sol);
+    }
+
+    #[tokio::test]
+    async fn test_fails_without_tokens() {
+        let rpc = RecordingRpc::new(SvmRpc::new());
+        let (payer, facilitator) = (Keypair::new(), Keypair::new());
+        let dir = TempDir::new();
+        let mut env_file = temp_env_file(&dir);
+        let options = FundOptions { xusd_amount: 0, ..Default::default() };
+
+        fund::run_with(
+            &rpc,
+            &Confirmer::default(),
+            &payer,
+            &facilitator,
+            &mut env_file,
+            &options,
+            &JsonReporter,
+        )
+        .await
+        .unwrap();
+        let mint =
+            Pubkey::from_str(env_file.get("XUSD_MINT").unwrap()).unwrap();
+        let sent = rpc.count(RpcMethod::SendTransaction);
+
+        let err = test::run_with(
+            &rpc,
+            &Confirmer::default(),
+            &payer,
+            &facilitator,
+            &mint,
+            &JsonReporter,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("Insufficient payer balance"),
+            "{}",
+            err
+        );
+        assert_eq!(error::classify(&err), ("insufficient_balance", 4));
+        assert_eq!(rpc.count(RpcMethod::SendTransaction), sent);
+    }
+
+    #[tokio::test]
+    async fn test_checks_the_payer_covers_the_token_account_rent() {
+        let rpc = SvmRpc::new();
+        let (payer, facilitator) = (Keypair::new(), Keypair::new());
+        let dir = TempDir::new();
+        let (_, mint) = fund_on(
+            &rpc,
+            &payer,
+            &facilitator,
+            &mut temp_env_file(&dir),
+            &FundOptions::default(),
+        )
+        .await;
+
+        // Drain the payer's SOL, and pay a facilitator without a token
+        // account, which the payer would have to fund
+        let lamports = rpc.get_balance(&payer.pubkey()).await.unwrap();
+        let drain = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &facilitator.pubkey(),
+                lamports - guard::LAMPORTS_PER_SIGNATURE,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            rpc.get_latest_blockhash().await.unwrap(),
+        );
+        rpc.send_and_confirm_transaction(&drain).await.unwrap();
+        let recipient = Keypair::new();
+        rpc.request_airdrop(&recipient.pubkey(), LAMPORTS_PER_SOL)
+            .await
+            .unwrap();
+
+        let err = test::run_with(
+            &rpc,
+            &Confirmer::default(),
+            &payer,
+            &recipient,
+            &mint.address,
+            &JsonReporter,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("PAYER_KEYPAIR"), "{}", err);
+        assert_eq!(error::classify(&err), ("insufficient_balance", 4));
+        let token_account = token::associated_token_address(
+            &recipient.pubkey(),
+            &mint.address,
+            mint.program,
+        );
+        assert!(rpc.get_account(&token_account).await.unwrap().is_none());
+    }
+}